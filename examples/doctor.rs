@@ -0,0 +1,25 @@
+// Writes a Gameboy Doctor trace of a ROM, to compare against its reference logs, e.g.
+//   cargo run --release --example doctor -- cpu_instrs/individual/01-special.gb 2000000 > 01.log
+//   python3 gameboy-doctor 01.log cpu_instrs 1
+use dmg::gb;
+use dmg::trace::TraceOptions;
+use std::io::{self, BufWriter};
+use std::{env, fs};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <rom> <instructions>", args[0]);
+        return;
+    }
+    let rom = fs::read(&args[1]).expect("Could not read ROM");
+    let instructions: u64 = args[2].parse().expect("Instruction count should be a number");
+
+    let mut gameboy = gb::init();
+    gameboy.memory.load_cartridge(rom);
+    gameboy.start_trace(Box::new(BufWriter::new(io::stdout())), TraceOptions::default());
+    while gameboy.instructions < instructions && gameboy.running {
+        gameboy.tick();
+    }
+    gameboy.stop_trace().expect("Could not write trace");
+}
@@ -0,0 +1,21 @@
+// Serves a ROM to a GDB remote protocol client, e.g.
+//   cargo run --example gdb -- game.gb 127.0.0.1:2345
+//   gdb -ex "target remote 127.0.0.1:2345"
+use dmg::gb;
+use dmg::gdb::GdbServer;
+use std::{env, fs};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <rom> <address>", args[0]);
+        return;
+    }
+    let rom = fs::read(&args[1]).expect("Could not read ROM");
+
+    let mut gameboy = gb::init();
+    gameboy.memory.load_cartridge(rom);
+    let server = GdbServer::bind(&args[2]).expect("Could not listen");
+    eprintln!("Waiting for a debugger on {}", server.local_addr().expect("Could not get address"));
+    server.serve(&mut gameboy).expect("Debugger connection failed");
+}
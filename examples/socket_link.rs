@@ -0,0 +1,53 @@
+// Runs a ROM headless with its serial port tunnelled over a socket, printing what it sends.
+// Start one process listening and one connecting, e.g.
+//   cargo run --example socket_link -- game.gb listen 127.0.0.1:5000
+//   cargo run --example socket_link -- game.gb connect 127.0.0.1:5000
+// On Unix, addresses starting with `unix:` use a Unix domain socket instead.
+use dmg::gb;
+use dmg::socket_link::SocketLink;
+use std::{env, fs, io};
+
+fn open_link(mode: &str, address: &str) -> io::Result<SocketLink> {
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        return match mode {
+            "listen" => SocketLink::listen_unix(path),
+            _ => SocketLink::connect_unix(path),
+        };
+    }
+    match mode {
+        "listen" => SocketLink::listen_tcp(address),
+        _ => SocketLink::connect_tcp(address),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        eprintln!("usage: {} <rom> <listen|connect> <host:port|unix:path>", args[0]);
+        return;
+    }
+    let rom = fs::read(&args[1]).expect("Could not read ROM");
+    let link = open_link(&args[2], &args[3]).expect("Could not open link socket");
+
+    let mut gameboy = gb::init();
+    gameboy.memory.load_cartridge(rom);
+    gameboy.skip_boot_rom();
+    gameboy.attach_serial_device(Box::new(link));
+    gameboy.capture_serial();
+
+    let mut printed = 0;
+    loop {
+        for _ in 0..17556 {
+            gameboy.tick();
+        }
+        let output = gameboy.serial_output();
+        if output.len() > printed {
+            for byte in output[printed..].chars() {
+                print!("{:02X} ", byte as u32);
+            }
+            println!();
+            printed = output.len();
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// BESS (Best Effort Save State), the save state footer shared between emulators.
+// https://github.com/LIJI32/SameBoy/blob/master/BESS.md
+//
+// The file ends with the offset of the first BESS block (LE u32) and "BESS". Blocks are a
+// 4 byte name, a LE u32 length and the contents; memory regions are stored elsewhere in the
+// file and referenced by size and offset from the CORE block.
+// save_bess appends BESS to a native save state, so the same file loads with load_state too.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use crate::util::*;
+
+const BESS_MAJOR: u16 = 1;
+const BESS_MINOR: u16 = 1;
+const CORE_SIZE: usize = 0xD0;
+const EMULATOR_NAME: &str = concat!("dmg v", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, PartialEq)]
+pub enum BessError {
+    NoFooter,
+    BadBlock(usize), // offset of the block that doesn't fit in the file
+    MissingCore,
+    UnsupportedVersion(u16),
+    BadRegion(usize), // CORE offset of a memory region pointing outside the file
+}
+
+// (memory address, size on DMG, CORE offset of its size/offset pair)
+const REGIONS: [(u16, usize, usize); 5] = [
+    (0xC000, 0x2000, 0x98), // RAM
+    (0x8000, 0x2000, 0xA0), // VRAM
+    (0xA000, 0x2000, 0xA8), // MBC RAM
+    (0xFE00, 0xA0, 0xB0), // OAM
+    (0xFF80, 0x7F, 0xB8), // HRAM
+];
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+}
+
+fn write_block(output: &mut Vec<u8>, name: &[u8; 4], contents: &[u8]) {
+    output.extend_from_slice(name);
+    output.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+    output.extend_from_slice(contents);
+}
+
+impl GameBoy {
+    fn mbc_ram_size(&self) -> usize {
+        match self.memory.cartridge.is_empty() || self.memory.read(0x0149) != 0 {
+            true => 0x2000,
+            false => 0,
+        }
+    }
+
+    pub fn save_bess(&self) -> Vec<u8> {
+        let mut output = self.save_state();
+
+        let mut core = vec![0u8; CORE_SIZE];
+        core[0x00..0x02].copy_from_slice(&BESS_MAJOR.to_le_bytes());
+        core[0x02..0x04].copy_from_slice(&BESS_MINOR.to_le_bytes());
+        core[0x04..0x08].copy_from_slice(b"GD  ");
+        let registers = [
+            self.registers.pc,
+            self.get_af(),
+            self.get_bc(),
+            self.get_de(),
+            self.get_hl(),
+            self.registers.sp,
+        ];
+        for (i, register) in registers.iter().enumerate() {
+            core[0x08 + i * 2..0x0A + i * 2].copy_from_slice(&register.to_le_bytes());
+        }
+        core[0x14] = self.ime as u8;
+        core[0x15] = self.memory.main[0xFFFF];
+        core[0x16] = match self.running {
+            true => 0,
+            false => 1, // halted
+        };
+        core[0x18..0x98].copy_from_slice(&self.memory.main[0xFF00..0xFF80]);
+        for (address, size, core_offset) in REGIONS {
+            let size = match address {
+                0xA000 => self.mbc_ram_size(),
+                _ => size,
+            };
+            let start = address as usize;
+            core[core_offset..core_offset + 4].copy_from_slice(&(size as u32).to_le_bytes());
+            core[core_offset + 4..core_offset + 8].copy_from_slice(&(output.len() as u32).to_le_bytes());
+            output.extend_from_slice(&self.memory.main[start..start + size]);
+        }
+
+        let first_block = output.len();
+        write_block(&mut output, b"NAME", EMULATOR_NAME.as_bytes());
+        if !self.memory.cartridge.is_empty() {
+            let mut info = Vec::new();
+            for address in 0x0134..0x0144 {
+                info.push(self.memory.read(address));
+            }
+            info.push(self.memory.read(0x014E));
+            info.push(self.memory.read(0x014F));
+            write_block(&mut output, b"INFO", &info);
+        }
+        write_block(&mut output, b"CORE", &core);
+        if !self.memory.cartridge.is_empty() {
+            // replaying the bank select write restores the mapping
+            let bank = self.memory.rom_bank as u8;
+            write_block(&mut output, b"MBC ", &[0x00, 0x20, bank]);
+        }
+        write_block(&mut output, b"END ", &[]);
+        output.extend_from_slice(&(first_block as u32).to_le_bytes());
+        output.extend_from_slice(b"BESS");
+        output
+    }
+
+    // Loads the parts of a BESS state this emulator has: DMG registers, memory, I/O and ROM bank.
+    // The cartridge the state was made with must already be loaded.
+    pub fn load_bess(&mut self, data: &[u8]) -> Result<(), BessError> {
+        if data.len() < 8 || &data[data.len() - 4..] != b"BESS" {
+            return Err(BessError::NoFooter);
+        }
+        let mut offset = read_u32(data, data.len() - 8);
+        let mut core = None;
+        let mut mbc_writes: &[u8] = &[];
+        loop {
+            if offset + 8 > data.len() {
+                return Err(BessError::BadBlock(offset));
+            }
+            let name = &data[offset..offset + 4];
+            let length = read_u32(data, offset + 4);
+            let contents = data.get(offset + 8..offset + 8 + length).ok_or(BessError::BadBlock(offset))?;
+            match name {
+                b"CORE" => core = Some((offset, contents)),
+                b"MBC " => mbc_writes = contents,
+                b"END " => break,
+                _ => (), // NAME, INFO, RTC and anything newer are informational or unsupported
+            }
+            offset += 8 + length;
+        }
+
+        let (core_block, core) = core.ok_or(BessError::MissingCore)?;
+        if core.len() < CORE_SIZE {
+            return Err(BessError::BadBlock(core_block));
+        }
+        let major = read_u16(core, 0x00);
+        if major != BESS_MAJOR {
+            return Err(BessError::UnsupportedVersion(major));
+        }
+        if &core[0x04..0x06] != b"GD" {
+            self.logger.log_warning("BESS: state is not from a DMG, loading what overlaps");
+        }
+        for (_, _, core_offset) in REGIONS {
+            let size = read_u32(core, core_offset);
+            let start = read_u32(core, core_offset + 4);
+            if start + size > data.len() {
+                return Err(BessError::BadRegion(core_offset));
+            }
+        }
+
+        self.registers.pc = read_u16(core, 0x08);
+        self.set_af(read_u16(core, 0x0A));
+        self.set_bc(read_u16(core, 0x0C));
+        self.set_de(read_u16(core, 0x0E));
+        self.set_hl(read_u16(core, 0x10));
+        self.registers.sp = read_u16(core, 0x12);
+        self.ime = core[0x14] != 0;
+        self.ime_dispatch = None;
+        self.memory.main[0xFFFF] = core[0x15];
+        self.running = core[0x16] == 0;
+        self.cycles_to_idle = Some(0);
+        self.isr.reset();
+        self.memory.main[0xFF00..0xFF80].copy_from_slice(&core[0x18..0x98]);
+
+        for (address, size, core_offset) in REGIONS {
+            let stored_size = read_u32(core, core_offset);
+            let start = read_u32(core, core_offset + 4);
+            let length = stored_size.min(size);
+            let address = address as usize;
+            self.memory.main[address..address + length].copy_from_slice(&data[start..start + length]);
+        }
+
+        for write in mbc_writes.chunks_exact(3) {
+            self.memory.write(unsigned_16(write[1], write[0]), write[2]);
+        }
+        self.serial.reset_transfer(); // BESS doesn't store it
+        self.finish_restore();
+        Ok(())
+    }
+}
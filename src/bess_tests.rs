@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod bess_test {
+    use crate::bess::BessError;
+    use crate::memory::Memory;
+    use crate::serial::SERIAL_CYCLES_PER_BIT;
+    use crate::util::{test_gameboy, test_gameboy_with, COUNTING_LOOP};
+
+    fn block(output: &mut Vec<u8>, name: &[u8; 4], contents: &[u8]) {
+        output.extend_from_slice(name);
+        output.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        output.extend_from_slice(contents);
+    }
+
+    // Laid out like SameBoy's: its own state first, then the memory regions, then the blocks
+    // including some this emulator doesn't know. `core_size` truncates the CORE block.
+    fn foreign_state(core_size: usize) -> Vec<u8> {
+        let mut data = b"SameBoy native state".to_vec();
+        let mut core = vec![0u8; 0xD0];
+        core[0x00..0x02].copy_from_slice(&1u16.to_le_bytes());
+        core[0x02..0x04].copy_from_slice(&1u16.to_le_bytes());
+        core[0x04..0x08].copy_from_slice(b"GDB ");
+        for (i, register) in [0x0100u16, 0x12B0, 0x3456, 0x789A, 0xC010, 0xFFFE].iter().enumerate() {
+            core[0x08 + i * 2..0x0A + i * 2].copy_from_slice(&register.to_le_bytes());
+        }
+        core[0x18 + 0x01] = 0x55; // SB
+        core[0x18 + 0x02] = 0x81; // SC, a transfer on the internal clock
+        core[0x18 + 0x40] = 0x91; // LCDC
+        // (size, fill byte, CORE offset) of RAM, VRAM, MBC RAM, OAM and HRAM
+        let regions = [(0x2000, 0xAA, 0x98), (0x2000, 0, 0xA0), (0, 0, 0xA8), (0xA0, 0, 0xB0), (0x7F, 0xBB, 0xB8)];
+        for (size, fill, core_offset) in regions {
+            core[core_offset..core_offset + 4].copy_from_slice(&(size as u32).to_le_bytes());
+            core[core_offset + 4..core_offset + 8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            data.resize(data.len() + size, fill);
+        }
+
+        let first_block = data.len();
+        block(&mut data, b"NAME", b"SameBoy v0.16.3");
+        block(&mut data, b"INFO", &[0; 0x12]);
+        block(&mut data, b"CORE", &core[..core_size]);
+        block(&mut data, b"XOAM", &[0; 0x60]);
+        block(&mut data, b"MBC ", &[0x00, 0x20, 0x02, 0x00, 0x00, 0x0A]);
+        block(&mut data, b"END ", &[]);
+        data.extend_from_slice(&(first_block as u32).to_le_bytes());
+        data.extend_from_slice(b"BESS");
+        data
+    }
+
+    #[test]
+    fn bess_round_trip() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        for _ in 0..40000 {
+            gameboy.tick();
+        }
+        let state = gameboy.save_bess();
+        assert_eq!(&state[state.len() - 4..], b"BESS");
+
+        let mut restored = test_gameboy(&COUNTING_LOOP);
+        restored.load_bess(&state).expect("Could not load BESS state");
+        assert_eq!(restored.registers.pc, gameboy.registers.pc);
+        assert_eq!(restored.get_af(), gameboy.get_af());
+        assert_eq!(restored.get_hl(), gameboy.get_hl());
+        assert!(restored.memory.main[0xC000..0xE000] == gameboy.memory.main[0xC000..0xE000]);
+        assert!(restored.memory.main[0xFF00..] == gameboy.memory.main[0xFF00..]);
+
+        // the native state in front of the footer still loads
+        restored.load_state(&state).expect("Could not load native state");
+        assert_eq!(restored.clock, gameboy.clock);
+
+        assert_eq!(restored.load_bess(&state[..state.len() - 1]), Err(BessError::NoFooter));
+    }
+
+    #[test]
+    fn loads_another_emulators_state() {
+        let mut gameboy = test_gameboy_with(&[(0x100, &[0x18, 0xFE]), (0x147, &[0x01])]); // JR -2, MBC1
+        gameboy.memory.write(0xFF01, 0x00);
+        gameboy.memory.write(0xFF02, 0x81);
+        for _ in 0..3 * SERIAL_CYCLES_PER_BIT as u32 {
+            gameboy.tick(); // partway through a transfer the state doesn't know about
+        }
+
+        gameboy.load_bess(&foreign_state(0xD0)).expect("Could not load BESS state");
+        assert_eq!((gameboy.registers.pc, gameboy.get_af(), gameboy.get_hl()), (0x0100, 0x12B0, 0xC010));
+        assert_eq!(gameboy.memory.main[0xC000], 0xAA);
+        assert_eq!(gameboy.memory.main[0xFF80], 0xBB);
+        assert_eq!(gameboy.memory.rom_bank, 2);
+
+        // the transfer in the state starts over instead of finishing the old one
+        for _ in 0..8 * SERIAL_CYCLES_PER_BIT as u32 - 1 {
+            gameboy.tick();
+        }
+        assert_ne!(gameboy.memory.read(0xFF02) & 0x80, 0, "transfer finished early");
+        gameboy.tick();
+        assert_eq!(gameboy.memory.read(0xFF02) & 0x80, 0);
+        assert_eq!(gameboy.memory.read(0xFF01), 0xFF);
+
+        let state = foreign_state(0x20);
+        let core_block = state.len() - 8 - (8 + 0x20 + 8 + 0x60 + 8 + 6 + 8);
+        assert_eq!(gameboy.load_bess(&state), Err(BessError::BadBlock(core_block)));
+    }
+}
@@ -0,0 +1,483 @@
+// Interactive debugger: loads a ROM and reads commands from stdin, e.g.
+//   cargo run --release --bin dmgdb -- game.gb
+// Addresses and values are hex ($ and 0x prefixes are optional), counts are decimal. Labels
+// from a .sym file next to the ROM can be used as addresses.
+// An empty line repeats the last command. Type help for the command list.
+use dmg::cdl;
+use dmg::debugger::{self, Condition, EXECUTE, READ, WRITE};
+use dmg::gb::{self, GameBoy};
+use dmg::memory::Memory;
+use dmg::run::StopReason;
+use dmg::search::{Filter, RamSearch, Width};
+use dmg::symbols;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, fs};
+
+const HELP: &str = "\
+step [n]                  run n instructions (s)
+next                      step over calls and rsts (n)
+finish                    run until the current routine returns
+backtrace                 the call stack (bt)
+continue                  run until a breakpoint, watchpoint, Ctrl-C or the CPU gets stuck (c)
+break <addr> [if <cond>]  break before addr or a label, e.g. break 150 if [c000] == 3 (b)
+watch <addr>[-<end>] [r|w|rw|x]
+                          stop on accesses to a range, writes by default
+delete <id>               remove a breakpoint or watchpoint
+breaks                    list breakpoints and watchpoints
+regs                      CPU registers (r)
+x/<n> <addr>              dump n bytes of memory
+disasm [addr] [n]         disassemble n instructions, from PC by default
+ppu                       PPU registers and mode
+io                        named I/O registers
+save <file>, load <file>  save states
+profile start|flat|tree, profile save <file>
+                          cycles per routine; save writes collapsed stacks for flamegraphs
+search new [8|16]         start a RAM search over cart RAM, WRAM and HRAM
+search same|changed|up|down|<value>
+                          keep the candidates that match, then list them
+cheat [add <code>|on <id>|off <id>|delete <id>]
+                          Game Genie and GameShark codes, lists them without arguments
+cdl start [file], cdl save <file>
+                          log which ROM bytes run as code or are read as data, in
+                          BizHawk's CDL format; start continues a saved log
+quit                      (q)";
+
+const IO_REGISTERS: [(u16, &str); 33] = [
+    (0xFF00, "P1"),
+    (0xFF01, "SB"),
+    (0xFF02, "SC"),
+    (0xFF04, "DIV"),
+    (0xFF05, "TIMA"),
+    (0xFF06, "TMA"),
+    (0xFF07, "TAC"),
+    (0xFF0F, "IF"),
+    (0xFF10, "NR10"),
+    (0xFF11, "NR11"),
+    (0xFF12, "NR12"),
+    (0xFF14, "NR14"),
+    (0xFF16, "NR21"),
+    (0xFF17, "NR22"),
+    (0xFF19, "NR24"),
+    (0xFF1A, "NR30"),
+    (0xFF1C, "NR32"),
+    (0xFF21, "NR42"),
+    (0xFF24, "NR50"),
+    (0xFF25, "NR51"),
+    (0xFF26, "NR52"),
+    (0xFF40, "LCDC"),
+    (0xFF41, "STAT"),
+    (0xFF42, "SCY"),
+    (0xFF43, "SCX"),
+    (0xFF44, "LY"),
+    (0xFF45, "LYC"),
+    (0xFF46, "DMA"),
+    (0xFF47, "BGP"),
+    (0xFF48, "OBP0"),
+    (0xFF49, "OBP1"),
+    (0xFF4A, "WY"),
+    (0xFF4B, "WX"),
+];
+
+// Set by Ctrl-C, which stops continue at the end of the frame instead of quitting
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// ctrlc runs the handler on its own thread, so all it has to do is raise the flag
+fn catch_interrupts() {
+    if let Err(error) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        eprintln!("Ctrl-C won't interrupt continue: {}", error);
+    }
+}
+
+fn describe(reason: StopReason) -> String {
+    match reason {
+        StopReason::Breakpoint(address) => format!("Breakpoint at ${:04X}", address),
+        StopReason::Watchpoint(hit) => {
+            let access = match hit.access {
+                READ => "read",
+                WRITE => "write",
+                _ => "execute",
+            };
+            format!("Watchpoint {}: {} of ${:02X} at ${:04X}", hit.id, access, hit.value, hit.address)
+        }
+        StopReason::HaltedForever => "The CPU halted and nothing can wake it".to_string(),
+        StopReason::IllegalOpcode(opcode) => format!("Illegal opcode ${:02X}", opcode),
+        _ => String::new(),
+    }
+}
+
+fn print_instruction(gameboy: &GameBoy, address: u16) -> u16 {
+    if let Some(label) = gameboy.symbol_at(address) {
+        println!("{}:", label);
+    }
+    let instruction = gameboy.disassemble(address);
+    let marker = if address == gameboy.registers.pc { ">" } else { " " };
+    println!("{} {:04X}  {}", marker, address, gameboy.format_instruction(&instruction));
+    instruction.next_address()
+}
+
+fn print_registers(gameboy: &GameBoy) {
+    let r = &gameboy.registers;
+    let flags: String = [(0x80, 'Z'), (0x40, 'N'), (0x20, 'H'), (0x10, 'C')]
+        .iter()
+        .map(|(bit, name)| if r.f & bit != 0 { *name } else { '-' })
+        .collect();
+    println!(
+        "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} [{}] IME={} cycles={}",
+        gameboy.get_af(),
+        gameboy.get_bc(),
+        gameboy.get_de(),
+        gameboy.get_hl(),
+        r.sp,
+        r.pc,
+        flags,
+        gameboy.ime as u8,
+        gameboy.clock
+    );
+}
+
+fn print_ppu(gameboy: &GameBoy) {
+    let read = |address| gameboy.memory.read(address);
+    let mode = match read(0xFF41) & 3 {
+        0 => "HBlank",
+        1 => "VBlank",
+        2 => "OAM scan",
+        _ => "Drawing",
+    };
+    let enabled = if read(0xFF40) & 0x80 != 0 { "on" } else { "off" };
+    println!(
+        "LCDC={:02X} ({}) STAT={:02X} ({}) LY={} LYC={} SCX={} SCY={} WX={} WY={} BGP={:02X} frames={}",
+        read(0xFF40),
+        enabled,
+        read(0xFF41),
+        mode,
+        read(0xFF44),
+        read(0xFF45),
+        read(0xFF43),
+        read(0xFF42),
+        read(0xFF4B),
+        read(0xFF4A),
+        read(0xFF47),
+        gameboy.frame_count
+    );
+}
+
+fn print_breaks(gameboy: &GameBoy) {
+    for breakpoint in &gameboy.debugger.breakpoints {
+        let condition = match breakpoint.condition {
+            Some(condition) => format!(" if {}", condition),
+            None => String::new(),
+        };
+        let enabled = if breakpoint.enabled { "" } else { " (disabled)" };
+        println!("{}: break ${:04X}{} hits={}{}", breakpoint.id, breakpoint.address, condition, breakpoint.hits, enabled);
+    }
+    for watchpoint in &gameboy.memory.watches.watchpoints {
+        let access: String = [(READ, 'r'), (WRITE, 'w'), (EXECUTE, 'x')]
+            .iter()
+            .filter(|(bit, _)| watchpoint.access & bit != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        let enabled = if watchpoint.enabled { "" } else { " (disabled)" };
+        println!("{}: watch ${:04X}-${:04X} {}{}", watchpoint.id, watchpoint.range.start(), watchpoint.range.end(), access, enabled);
+    }
+}
+
+// A label or a number
+fn address(gameboy: &GameBoy, text: Option<&str>) -> Result<u16, String> {
+    let text = text.ok_or("Missing address")?;
+    match gameboy.symbols.lookup(text) {
+        Some((_, address)) => Ok(address),
+        None => debugger::parse_number(text).ok_or_else(|| format!("Bad address {}", text)),
+    }
+}
+
+fn count(text: Option<&str>, default: u32) -> Result<u32, String> {
+    match text {
+        Some(text) => text.parse().map_err(|_| format!("Bad count {}", text)),
+        None => Ok(default),
+    }
+}
+
+// Runs one command, returning false to quit
+fn execute(gameboy: &mut GameBoy, search: &mut Option<RamSearch>, line: &str) -> Result<bool, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(true),
+    };
+    let reason = match command {
+        "step" | "s" => {
+            let mut reason = StopReason::InstructionDone;
+            for _ in 0..count(words.next(), 1)? {
+                reason = gameboy.step_into();
+                if reason != StopReason::InstructionDone {
+                    break;
+                }
+            }
+            Some(reason)
+        }
+        "next" | "n" => Some(gameboy.step_over()),
+        "finish" => Some(gameboy.step_out()),
+        "continue" | "c" => {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+            loop {
+                match gameboy.run_frame() {
+                    StopReason::FrameDone | StopReason::CyclesElapsed if INTERRUPTED.load(Ordering::SeqCst) => {
+                        println!("Interrupted");
+                        break Some(StopReason::FrameDone);
+                    }
+                    StopReason::FrameDone | StopReason::CyclesElapsed => (),
+                    reason => break Some(reason),
+                }
+            }
+        }
+        "break" | "b" => {
+            let target = words.next();
+            let condition = match words.next() {
+                Some("if") => {
+                    let text = words.collect::<Vec<_>>().join(" ");
+                    Some(Condition::parse(&text).ok_or_else(|| format!("Bad condition {}", text))?)
+                }
+                Some(other) => return Err(format!("Expected if, not {}", other)),
+                None => None,
+            };
+            let id = match target.and_then(|name| gameboy.add_symbol_breakpoint(name, condition)) {
+                Some(id) => id,
+                None => gameboy.add_breakpoint(address(gameboy, target)?, condition),
+            };
+            println!("Breakpoint {}", id);
+            None
+        }
+        "watch" => {
+            let range = words.next().ok_or("Missing address")?;
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (address(gameboy, Some(start))?, address(gameboy, Some(end))?),
+                None => (address(gameboy, Some(range))?, address(gameboy, Some(range))?),
+            };
+            let access = match words.next().unwrap_or("w") {
+                "r" => READ,
+                "w" => WRITE,
+                "rw" => READ | WRITE,
+                "x" => EXECUTE,
+                other => return Err(format!("Bad access {}", other)),
+            };
+            println!("Watchpoint {}", gameboy.add_watchpoint(start..=end, access));
+            None
+        }
+        "delete" => {
+            let id = count(words.next(), 0)?;
+            if !gameboy.remove_break(id) {
+                return Err(format!("No breakpoint or watchpoint {}", id));
+            }
+            None
+        }
+        "breaks" => {
+            print_breaks(gameboy);
+            None
+        }
+        "backtrace" | "bt" => {
+            for line in gameboy.backtrace() {
+                println!("{}", line);
+            }
+            None
+        }
+        "regs" | "r" => {
+            print_registers(gameboy);
+            None
+        }
+        "disasm" => {
+            let mut next = match words.next() {
+                Some(text) => address(gameboy, Some(text))?,
+                None => gameboy.registers.pc,
+            };
+            for _ in 0..count(words.next(), 10)? {
+                next = print_instruction(gameboy, next);
+            }
+            None
+        }
+        "ppu" => {
+            print_ppu(gameboy);
+            None
+        }
+        "io" => {
+            for (address, name) in IO_REGISTERS {
+                println!("{:04X} {:<4} = {:02X}", address, name, gameboy.memory.read(address));
+            }
+            println!("FFFF IE   = {:02X}", gameboy.memory.read(0xFFFF));
+            None
+        }
+        "save" => {
+            let path = words.next().ok_or("Missing file")?;
+            fs::write(path, gameboy.save_state()).map_err(|error| format!("Could not write {}: {}", path, error))?;
+            None
+        }
+        "load" => {
+            let path = words.next().ok_or("Missing file")?;
+            let data = fs::read(path).map_err(|error| format!("Could not read {}: {}", path, error))?;
+            gameboy.load_state(&data).map_err(|error| format!("Could not load {}: {:?}", path, error))?;
+            print_instruction(gameboy, gameboy.registers.pc);
+            None
+        }
+        "profile" => {
+            let subcommand = words.next();
+            if subcommand == Some("start") {
+                gameboy.start_profiling();
+                return Ok(true);
+            }
+            let profile = gameboy.profile().ok_or("Not profiling, use profile start")?;
+            match (subcommand, words.next()) {
+                (Some("flat"), _) => print!("{}", profile.flat_report()),
+                (Some("tree"), _) => print!("{}", profile.tree_report()),
+                (Some("save"), Some(path)) => {
+                    fs::write(path, profile.collapsed()).map_err(|error| format!("Could not write {}: {}", path, error))?
+                }
+                _ => return Err("Expected profile start, flat, tree or save <file>".to_string()),
+            }
+            None
+        }
+        "search" => {
+            let filter = match words.next() {
+                Some("new") => {
+                    let width = match words.next() {
+                        Some("16") => Width::Word,
+                        _ => Width::Byte,
+                    };
+                    let started = search.insert(RamSearch::new(gameboy, width));
+                    println!("{} candidates", started.len());
+                    return Ok(true);
+                }
+                Some("same") => Filter::Unchanged,
+                Some("changed") => Filter::Changed,
+                Some("up") => Filter::Increased,
+                Some("down") => Filter::Decreased,
+                Some(value) => Filter::Value(debugger::parse_number(value).ok_or_else(|| format!("Bad value {}", value))?),
+                None => return Err("Expected search new, same, changed, up, down or a value".to_string()),
+            };
+            let search = search.as_mut().ok_or("No search, use search new")?;
+            let left = search.filter(gameboy, filter);
+            for candidate in search.results(gameboy).iter().take(20) {
+                println!("{:04X} = {:X} (was {:X})", candidate.address, candidate.value, candidate.previous);
+            }
+            if left > 20 {
+                println!("... {} candidates", left);
+            }
+            None
+        }
+        "cheat" => {
+            let found = match (words.next(), words.next()) {
+                (None, _) => {
+                    for cheat in &gameboy.memory.cheats.list {
+                        let enabled = if cheat.enabled { "" } else { " (disabled)" };
+                        println!("{}: {}{}", cheat.id, cheat.code, enabled);
+                    }
+                    true
+                }
+                (Some("add"), Some(code)) => {
+                    let id = gameboy.add_cheat(code).map_err(|error| format!("Bad code {}: {:?}", code, error))?;
+                    println!("Cheat {}", id);
+                    true
+                }
+                (Some("on"), id) => gameboy.set_cheat_enabled(count(id, 0)?, true),
+                (Some("off"), id) => gameboy.set_cheat_enabled(count(id, 0)?, false),
+                (Some("delete"), id) => gameboy.remove_cheat(count(id, 0)?),
+                _ => return Err("Expected cheat add <code>, on, off or delete <id>".to_string()),
+            };
+            if !found {
+                return Err("No such cheat".to_string());
+            }
+            None
+        }
+        "cdl" => {
+            match (words.next(), words.next()) {
+                (Some("start"), path) => {
+                    let previous = path
+                        .map(|path| fs::read(path).map_err(|error| format!("Could not read {}: {}", path, error)))
+                        .transpose()?;
+                    gameboy.start_cdl(previous.as_deref()).map_err(|error| format!("Could not load the log: {:?}", error))?;
+                }
+                (Some("save"), Some(path)) => {
+                    let log = gameboy.memory.cdl.as_ref().ok_or("Not logging, use cdl start")?;
+                    fs::write(path, log.to_bytes()).map_err(|error| format!("Could not write {}: {}", path, error))?;
+                    let code = log.count(cdl::EXEC_FIRST | cdl::EXEC_OPERAND);
+                    println!("{} bytes of code, {} of data, {} of DMA sources", code, log.count(cdl::DATA), log.count(cdl::DMA_SOURCE));
+                }
+                _ => return Err("Expected cdl start [file] or cdl save <file>".to_string()),
+            }
+            None
+        }
+        "help" | "h" => {
+            println!("{}", HELP);
+            None
+        }
+        "quit" | "q" => return Ok(false),
+        _ => match command.strip_prefix("x/") {
+            Some(length) => {
+                let length = count(Some(length), 0)?;
+                let start = address(gameboy, words.next())?;
+                for row in (0..length).step_by(16) {
+                    let address = start.wrapping_add(row as u16);
+                    let bytes: Vec<String> = (row..length.min(row + 16))
+                        .map(|i| format!("{:02X}", gameboy.memory.read(start.wrapping_add(i as u16))))
+                        .collect();
+                    println!("{:04X}: {}", address, bytes.join(" "));
+                }
+                None
+            }
+            None => return Err(format!("Unknown command {}, try help", command)),
+        },
+    };
+    if let Some(reason) = reason {
+        let description = describe(reason);
+        if !description.is_empty() {
+            println!("{}", description);
+        }
+        print_instruction(gameboy, gameboy.registers.pc);
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 && args.len() != 3 {
+        eprintln!("usage: {} <rom> [ips|ups|bps patch]", args[0]);
+        return;
+    }
+    let rom = fs::read(&args[1]).expect("Could not read ROM");
+    let mut gameboy = gb::init();
+    match args.get(2) {
+        Some(path) => {
+            let patch = fs::read(path).expect("Could not read patch");
+            gameboy.load_patched_cartridge(&rom, &patch).expect("Could not apply patch");
+        }
+        None => gameboy.memory.load_cartridge(rom),
+    }
+    gameboy.skip_boot_rom();
+    if let Ok(text) = fs::read_to_string(symbols::path_for(Path::new(&args[1]))) {
+        gameboy.load_symbols(&text);
+        println!("Loaded {} symbols", gameboy.symbols.len());
+    }
+    print_instruction(&gameboy, gameboy.registers.pc);
+    catch_interrupts();
+
+    let stdin = io::stdin();
+    let mut last = String::new();
+    let mut search = None;
+    loop {
+        print!("(dmg) ");
+        io::stdout().flush().expect("Could not write to stdout");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).expect("Could not read stdin") == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last.clone();
+        }
+        match execute(&mut gameboy, &mut search, &line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(message) => println!("{}", message),
+        }
+        last = line;
+    }
+}
@@ -0,0 +1,49 @@
+// https://github.com/retrio/gb-test-roms
+// Blargg's test ROMs report their results over the serial port
+#[cfg(test)]
+mod blargg_test {
+    use crate::gb;
+    use crate::util::test_gameboy_with;
+    use std::{fs, path::Path};
+
+    const MAX_CYCLES: u64 = 60 * 17556 * 60; // a minute of emulated time
+
+    fn run(mut gameboy: gb::GameBoy) -> (Option<usize>, String) {
+        let result = gameboy.run_until_serial_contains(&["Passed", "Failed"], MAX_CYCLES);
+        (result, gameboy.serial_output().to_string())
+    }
+
+    // 02-interrupts needs the timer, which isn't emulated yet. The pattern is matched against the
+    // whole path, so it anchors on the file name.
+    #[datatest::files("gb-test-roms/cpu_instrs/individual", {
+        path in r"/(0[013-9]|1[01])[^/]*\.gb$",
+    })]
+    fn datatest_run_cpu_instrs(path: &Path) {
+        let rom = fs::read(path).expect("Could not read test ROM");
+        let mut gameboy = gb::init();
+        gameboy.memory.load_cartridge(rom);
+        gameboy.skip_boot_rom();
+        let (result, output) = run(gameboy);
+        assert_eq!(result, Some(0), "{:?} did not pass, serial output:\n{}", path.file_name().unwrap(), output);
+    }
+
+    #[test]
+    fn captures_serial_output() {
+        let program = [
+            0x21, 0x50, 0x01, // LD HL, 0x0150
+            0x2A, // LD A, (HL+)
+            0xB7, // OR A
+            0x28, 0xFE, // JR Z, -2
+            0xE0, 0x01, // LDH (0x01), A
+            0x3E, 0x81, // LD A, 0x81
+            0xE0, 0x02, // LDH (0x02), A
+            0xF0, 0x02, // LDH A, (0x02)
+            0xE6, 0x80, // AND 0x80
+            0x20, 0xFA, // JR NZ, -6
+            0x18, 0xEE, // JR -18
+        ];
+        let (result, output) = run(test_gameboy_with(&[(0x100, &program), (0x150, b"Passed\n\0")]));
+        assert_eq!(result, Some(0));
+        assert_eq!(output, "Passed");
+    }
+}
@@ -0,0 +1,97 @@
+// Shadow call stack: CALL, RST and interrupt dispatch push a frame, so a debugger can show how
+// execution got where it is without unwinding the real stack.
+//
+// Returns aren't tracked directly. A frame lives while SP is at or below the slot its return
+// address was pushed to, so RET and RETI drop it, and so do games that discard return addresses
+// with POP or ADD SP or reload SP. Code that switches to a new stack lower in memory keeps the
+// old frames until SP passes back over them.
+use crate::gb::GameBoy;
+
+const MAX_FRAMES: usize = 256; // the oldest frames go first if a game never unwinds
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameCause {
+    Call,
+    Rst(u8),
+    Interrupt(u16), // the vector
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub cause: FrameCause,
+    pub target: u16, // the routine entered
+    pub bank: Option<u16>, // ROM bank of target, see MappedRAM::bank
+    pub return_address: u16,
+    pub return_bank: Option<u16>,
+    pub sp: u16, // where the return address was pushed
+}
+
+#[derive(Default)]
+pub struct CallStack {
+    frames: Vec<Frame>, // outermost first
+}
+
+impl CallStack {
+    // Drops the frames SP has moved back above
+    fn unwind(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp < sp) {
+            self.frames.pop();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl GameBoy {
+    // Called once a call, RST or interrupt has pushed `return_address` and jumped
+    pub(crate) fn enter_frame(&mut self, cause: FrameCause, return_address: u16) {
+        let sp = self.registers.sp;
+        let stack = &mut self.shadow_stack;
+        stack.unwind(sp.wrapping_add(2));
+        if stack.frames.len() == MAX_FRAMES {
+            stack.frames.remove(0);
+        }
+        stack.frames.push(Frame {
+            cause,
+            target: self.registers.pc,
+            bank: self.memory.bank(self.registers.pc),
+            return_address,
+            return_bank: self.memory.bank(return_address),
+            sp,
+        });
+    }
+
+    // Frames still live at the current SP, innermost first
+    pub fn call_stack(&self) -> Vec<Frame> {
+        let sp = self.registers.sp;
+        let live = self.shadow_stack.frames.iter().rposition(|frame| frame.sp >= sp).map_or(0, |i| i + 1);
+        self.shadow_stack.frames[..live].iter().rev().copied().collect()
+    }
+
+    // One line per frame, innermost first, starting with PC, e.g.
+    //   #0 $4003 BankedRoutine+$3
+    //   #1 $0108 Main+$8 (called $4000 in bank 2)
+    pub fn backtrace(&self) -> Vec<String> {
+        let describe = |address: u16, bank: Option<u16>| match self.symbols.describe(address, bank) {
+            Some(label) => format!("${:04X} {}", address, label),
+            None => format!("${:04X}", address),
+        };
+        let pc = self.registers.pc;
+        let mut lines = vec![format!("#0 {}", describe(pc, self.memory.bank(pc)))];
+        for (i, frame) in self.call_stack().iter().enumerate() {
+            let bank = match frame.bank {
+                Some(bank) => format!(" in bank {}", bank),
+                None => String::new(),
+            };
+            let entry = match frame.cause {
+                FrameCause::Call => format!("called ${:04X}{}", frame.target, bank),
+                FrameCause::Rst(vector) => format!("rst ${:02X}", vector),
+                FrameCause::Interrupt(vector) => format!("interrupt ${:02X}", vector),
+            };
+            lines.push(format!("#{} {} ({})", i + 1, describe(frame.return_address, frame.return_bank), entry));
+        }
+        lines
+    }
+}
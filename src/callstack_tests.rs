@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod callstack_test {
+    use crate::callstack::FrameCause;
+    use crate::run::StopReason;
+    use crate::util::test_gameboy_with;
+
+    #[test]
+    fn calls_and_discarded_returns() {
+        let mut gameboy = test_gameboy_with(&[
+            (0x0008, &[0xCD, 0x20, 0x01, 0xC9]), // 0008: CALL 0x0120, RET
+            (0x0100, &[0xCD, 0x10, 0x01, 0x18, 0xFE]), // 0100: CALL 0x0110, JR -2
+            (0x0110, &[0xCF, 0xC9]), // 0110: RST 0x08, RET
+            (0x0120, &[0x3C, 0xE1, 0x18, 0xFE]), // 0120: INC A, POP HL, JR -2
+        ]);
+        gameboy.load_symbols("00:0100 Main\n00:0110 Helper\n00:0120 Leaf\n");
+        gameboy.add_breakpoint(0x0120, None);
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x0120));
+
+        let causes: Vec<(FrameCause, u16)> = gameboy.call_stack().iter().map(|frame| (frame.cause, frame.return_address)).collect();
+        assert_eq!(causes, [(FrameCause::Call, 0x000B), (FrameCause::Rst(0x08), 0x0111), (FrameCause::Call, 0x0103)]);
+        assert_eq!(gameboy.call_stack()[0].sp, 0xFFF8);
+        assert_eq!(gameboy.backtrace(), [
+            "#0 $0120 Leaf",
+            "#1 $000B (called $0120)",
+            "#2 $0111 Helper+$1 (rst $08)",
+            "#3 $0103 Main+$3 (called $0110)",
+        ]);
+
+        // POP HL throws away the return address to 0x000B, which drops its frame
+        gameboy.step_into();
+        gameboy.step_into();
+        assert_eq!(gameboy.call_stack().len(), 2);
+        assert_eq!(gameboy.call_stack()[0].return_address, 0x0111);
+    }
+
+    #[test]
+    fn interrupts_and_returns() {
+        let mut gameboy = test_gameboy_with(&[
+            (0x0040, &[0xD9]), // 0040: RETI
+            (0x0100, &[0xFB, 0x00, 0x00, 0x18, 0xFE]), // 0100: EI, NOP, NOP, JR -2
+        ]);
+        gameboy.memory.main[0xFFFF] = 0x01;
+        gameboy.memory.main[0xFF0F] = 0x01;
+        gameboy.add_breakpoint(0x0040, None);
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x0040));
+        let frame = gameboy.call_stack()[0];
+        assert_eq!((frame.cause, frame.target), (FrameCause::Interrupt(0x40), 0x0040));
+
+        gameboy.step_into();
+        assert!(gameboy.call_stack().is_empty());
+    }
+}
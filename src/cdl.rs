@@ -0,0 +1,154 @@
+// Code/Data Logger: a flag byte for every byte of the cartridge ROM, in ROM file order (bank by
+// bank), recording how it was used: the opcode and operand bytes of each instruction fetched from
+// ROM, every other CPU read of ROM as data, and the bytes OAM DMA copied out of ROM.
+//
+// Saved logs use BizHawk's CDL container with the Game Boy subtype:
+//   "BIZHAWK-CDL-2", the subtype "GB" padded to 15 characters, the block count (LE i32)
+//   then per block its name, its length (LE i32) and its bytes
+// Strings are .NET BinaryWriter ones: the length in 7-bit groups, low first with bit 7 set on
+// all but the last, then the characters. RAM isn't logged, so there's just a "ROM" block, which
+// lines up with the ROM file for disassemblers.
+use crate::disasm;
+use crate::gb::GameBoy;
+use crate::util::{ReadError, Reader};
+use std::cell::Cell;
+use std::ops::RangeInclusive;
+
+pub const EXEC_FIRST: u8 = 0x01; // first byte of an executed instruction
+pub const EXEC_OPERAND: u8 = 0x02; // later bytes of an executed instruction
+pub const DATA: u8 = 0x04; // read by an instruction
+pub const DMA_SOURCE: u8 = 0x08; // copied to OAM by DMA, i.e. sprite data
+
+pub const CDL_MAGIC: &str = "BIZHAWK-CDL-2";
+const SUBTYPE: &str = "GB";
+const ROM_BLOCK: &str = "ROM";
+
+#[derive(Debug, PartialEq)]
+pub enum CdlError {
+    BadMagic,
+    Truncated,
+    NoRomBlock,
+}
+
+impl From<ReadError> for CdlError {
+    fn from(_: ReadError) -> CdlError {
+        CdlError::Truncated
+    }
+}
+
+fn write_string(output: &mut Vec<u8>, string: &str) {
+    let mut length = string.len();
+    while length >= 0x80 {
+        output.push(0x80 | (length & 0x7F) as u8);
+        length >>= 7;
+    }
+    output.push(length as u8);
+    output.extend_from_slice(string.as_bytes());
+}
+
+fn read_string<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8], CdlError> {
+    let mut length = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = reader.u8()?;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(reader.take(length)?);
+        }
+    }
+    Err(CdlError::Truncated) // longer than an i32 can be
+}
+
+pub struct CodeDataLog {
+    flags: Vec<Cell<u8>>, // cells, as reads only borrow memory
+    pub(crate) instruction: Option<RangeInclusive<u16>>, // bytes of the executing instruction, not data
+}
+
+impl CodeDataLog {
+    pub fn new(rom_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            flags: vec![Cell::new(0); rom_size],
+            instruction: None,
+        }
+    }
+
+    // Continues a saved log, which must be for a ROM of the same size. Blocks other than the ROM's
+    // are skipped.
+    pub fn from_bytes(data: &[u8]) -> Result<CodeDataLog, CdlError> {
+        let mut reader = Reader::new(data, 0);
+        if read_string(&mut reader)? != CDL_MAGIC.as_bytes() {
+            return Err(CdlError::BadMagic);
+        }
+        read_string(&mut reader)?; // subtype
+        let mut rom = None;
+        for _ in 0..reader.u32()? {
+            let name = read_string(&mut reader)?;
+            let length = reader.u32()? as usize;
+            let contents = reader.take(length)?;
+            if name == ROM_BLOCK.as_bytes() {
+                rom = Some(contents);
+            }
+        }
+        Ok(CodeDataLog {
+            flags: rom.ok_or(CdlError::NoRomBlock)?.iter().map(|flags| Cell::new(*flags)).collect(),
+            instruction: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        write_string(&mut output, CDL_MAGIC);
+        write_string(&mut output, &format!("{:<15}", SUBTYPE));
+        output.extend_from_slice(&1u32.to_le_bytes());
+        write_string(&mut output, ROM_BLOCK);
+        output.extend_from_slice(&(self.flags.len() as u32).to_le_bytes());
+        output.extend(self.flags.iter().map(Cell::get));
+        output
+    }
+
+    pub(crate) fn mark(&self, offset: usize, flag: u8) {
+        if let Some(flags) = self.flags.get(offset) {
+            flags.set(flags.get() | flag);
+        }
+    }
+
+    pub fn flags(&self, offset: usize) -> u8 {
+        self.flags.get(offset).map_or(0, Cell::get)
+    }
+
+    // How many bytes have any of these flags set, e.g. for coverage
+    pub fn count(&self, flag: u8) -> usize {
+        self.flags.iter().filter(|flags| flags.get() & flag != 0).count()
+    }
+}
+
+impl GameBoy {
+    // Starts logging, continuing from a saved log if given; nothing changes if it doesn't parse
+    pub fn start_cdl(&mut self, previous: Option<&[u8]>) -> Result<(), CdlError> {
+        self.memory.cdl = Some(match previous {
+            Some(data) => CodeDataLog::from_bytes(data)?,
+            None => CodeDataLog::new(self.memory.cartridge.len()),
+        });
+        Ok(())
+    }
+
+    pub fn stop_cdl(&mut self) -> Option<Vec<u8>> {
+        self.memory.cdl.take().map(|cdl| cdl.to_bytes())
+    }
+
+    // Marks the instruction about to execute at PC
+    pub(crate) fn log_code(&mut self) {
+        let pc = self.registers.pc;
+        let length = disasm::decode_at(&self.memory, pc).length as u16;
+        let last = pc.wrapping_add(length - 1);
+        for i in 0..length {
+            let address = pc.wrapping_add(i);
+            let flag = if i == 0 { EXEC_FIRST } else { EXEC_OPERAND };
+            if let (Some(offset), Some(cdl)) = (self.memory.rom_offset(address), self.memory.cdl.as_ref()) {
+                cdl.mark(offset, flag);
+            }
+        }
+        if let Some(cdl) = self.memory.cdl.as_mut() {
+            cdl.instruction = Some(pc..=last);
+        }
+    }
+}
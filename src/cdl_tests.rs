@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod cdl_test {
+    use crate::cdl::{CdlError, CodeDataLog, DATA, DMA_SOURCE, EXEC_FIRST, EXEC_OPERAND};
+    use crate::gb;
+    use crate::run::StopReason;
+
+    #[test]
+    fn logs_code_and_data_per_bank() {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0x0147] = 0x01; // MBC1
+        let program = [
+            0xFA, 0x00, 0x02, // 0100: LD A, (0x0200)
+            0x3E, 0x02, // 0103: LD A, 2
+            0xEA, 0x00, 0x20, // 0105: LD (0x2000), A
+            0xCD, 0x00, 0x40, // 0108: CALL 0x4000
+            0x18, 0xFE, // 010B: JR -2
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        // bank 2: LD A, (0x4010); LD A, 0x50; LDH (DMA), A; RET
+        rom[0x8000..0x8008].copy_from_slice(&[0xFA, 0x10, 0x40, 0x3E, 0x50, 0xE0, 0x46, 0xC9]);
+        let mut gameboy = gb::init();
+        gameboy.memory.load_cartridge(rom);
+        gameboy.skip_boot_rom();
+        gameboy.start_cdl(None).unwrap();
+        gameboy.add_breakpoint(0x010B, None);
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x010B));
+
+        let saved = gameboy.stop_cdl().unwrap();
+        let mut header = b"\x0DBIZHAWK-CDL-2\x0FGB             \x01\x00\x00\x00\x03ROM".to_vec();
+        header.extend_from_slice(&0x10000u32.to_le_bytes());
+        assert_eq!(saved[..header.len()], header[..]);
+        assert_eq!(saved.len(), header.len() + 0x10000);
+
+        let log = CodeDataLog::from_bytes(&saved).unwrap();
+        assert_eq!([log.flags(0x100), log.flags(0x101), log.flags(0x102)], [EXEC_FIRST, EXEC_OPERAND, EXEC_OPERAND]);
+        assert_eq!(log.flags(0x10B), 0); // not reached yet
+        assert_eq!(log.flags(0x200), DATA);
+        let bank_2: Vec<u8> = (0x8000..0x8008).map(|offset| log.flags(offset)).collect();
+        let (first, operand) = (EXEC_FIRST, EXEC_OPERAND);
+        assert_eq!(bank_2, [first, operand, operand, first, operand, first, operand, first]);
+        assert_eq!(log.flags(0x8010), DATA);
+        assert_eq!(log.flags(0x4000), 0); // bank 1 was never mapped while running
+        assert_eq!(log.count(DATA), 2);
+        assert_eq!((log.flags(0x9000), log.flags(0x909F), log.flags(0x90A0)), (DMA_SOURCE, DMA_SOURCE, 0));
+        assert_eq!(log.count(DMA_SOURCE), 0xA0);
+        assert_eq!(CodeDataLog::from_bytes(b"\x04ROM!").err(), Some(CdlError::BadMagic));
+        assert_eq!(CodeDataLog::from_bytes(&saved[..saved.len() - 1]).err(), Some(CdlError::Truncated));
+
+        // a saved log keeps accumulating
+        gameboy.start_cdl(Some(&saved)).unwrap();
+        gameboy.step_into();
+        let cdl = gameboy.memory.cdl.as_ref().unwrap();
+        assert_eq!(cdl.flags(0x10B), EXEC_FIRST);
+        assert_eq!(cdl.count(EXEC_FIRST), 9);
+    }
+}
@@ -0,0 +1,125 @@
+// Cheat codes. Game Genie codes patch what the CPU reads from ROM, optionally only where the
+// original byte matches. GameShark codes write RAM every frame, as VBlank starts.
+//
+// Game Genie: ABC-DEF or ABC-DEF-GHI in hex. AB is the new value, and the address is FCDE with
+// F XORed with 0xF. GI is the compare value, rotated right by 2 and XORed with 0xBA; H is unused.
+// GameShark: ABCDEFGH in hex. AB is the RAM bank (01 without banking), CD the value and GHEF the
+// address. RAM banks aren't emulated, so the bank is kept but not checked.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CheatKind {
+    GameGenie { address: u16, value: u8, compare: Option<u8> },
+    GameShark { bank: u8, address: u16, value: u8 },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Cheat {
+    pub id: u32,
+    pub code: String,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    BadLength(usize), // of the code without dashes
+    BadDigit(char),
+    NotRom(u16), // a Game Genie address outside 0x0000-0x7FFF
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Cheats {
+    pub list: Vec<Cheat>,
+    next_id: u32,
+}
+
+// Decodes a Game Genie or GameShark code, telling them apart by their length
+pub fn decode(code: &str) -> Result<CheatKind, CheatError> {
+    let digits = code
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_digit(16).map(|digit| digit as u8).ok_or(CheatError::BadDigit(c)))
+        .collect::<Result<Vec<u8>, CheatError>>()?;
+    let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+    match digits.len() {
+        6 | 9 => {
+            let address = ((digits[5] ^ 0xF) as u16) << 12 | (digits[2] as u16) << 8 | byte(3) as u16;
+            if address > 0x7FFF {
+                return Err(CheatError::NotRom(address));
+            }
+            let compare = match digits.len() {
+                9 => Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA),
+                _ => None,
+            };
+            Ok(CheatKind::GameGenie { address, value: byte(0), compare })
+        }
+        8 => Ok(CheatKind::GameShark {
+            bank: byte(0),
+            address: u16::from_le_bytes([byte(4), byte(6)]),
+            value: byte(2),
+        }),
+        length => Err(CheatError::BadLength(length)),
+    }
+}
+
+impl Cheats {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    // What the CPU reads from ROM at `address` where the cartridge has `original`
+    pub(crate) fn patch_rom(&self, address: u16, original: u8) -> u8 {
+        for cheat in self.list.iter().filter(|cheat| cheat.enabled) {
+            if let CheatKind::GameGenie { address: patched, value, compare } = cheat.kind {
+                if patched == address && compare.is_none_or(|compare| compare == original) {
+                    return value;
+                }
+            }
+        }
+        original
+    }
+}
+
+impl GameBoy {
+    pub fn add_cheat(&mut self, code: &str) -> Result<u32, CheatError> {
+        let kind = decode(code)?;
+        let cheats = &mut self.memory.cheats;
+        cheats.next_id += 1;
+        cheats.list.push(Cheat {
+            id: cheats.next_id,
+            code: code.to_string(),
+            kind,
+            enabled: true,
+        });
+        Ok(cheats.next_id)
+    }
+
+    pub fn remove_cheat(&mut self, id: u32) -> bool {
+        let count = self.memory.cheats.list.len();
+        self.memory.cheats.list.retain(|cheat| cheat.id != id);
+        count != self.memory.cheats.list.len()
+    }
+
+    pub fn set_cheat_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        match self.memory.cheats.list.iter_mut().find(|cheat| cheat.id == id) {
+            Some(cheat) => {
+                cheat.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Called as VBlank starts
+    pub(crate) fn apply_gameshark_codes(&mut self) {
+        for i in 0..self.memory.cheats.list.len() {
+            let cheat = &self.memory.cheats.list[i];
+            if let (true, CheatKind::GameShark { address, value, .. }) = (cheat.enabled, cheat.kind) {
+                self.memory.write(address, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod cheats_test {
+    use crate::cheats::{self, CheatError, CheatKind};
+    use crate::memory::Memory;
+    use crate::run::StopReason;
+    use crate::util::test_gameboy_with;
+
+    #[test]
+    fn decoding() {
+        assert_eq!(cheats::decode("3E1-50F-EBA"), Ok(CheatKind::GameGenie { address: 0x0150, value: 0x3E, compare: Some(0x00) }));
+        assert_eq!(cheats::decode("3e1-50f"), Ok(CheatKind::GameGenie { address: 0x0150, value: 0x3E, compare: None }));
+        assert_eq!(cheats::decode("016304C1"), Ok(CheatKind::GameShark { bank: 1, address: 0xC104, value: 0x63 }));
+        assert_eq!(cheats::decode("123"), Err(CheatError::BadLength(3)));
+        assert_eq!(cheats::decode("01FG04C1"), Err(CheatError::BadDigit('G')));
+        assert_eq!(cheats::decode("3E1-507"), Err(CheatError::NotRom(0x8150)));
+    }
+
+    #[test]
+    fn applying_codes() {
+        let mut gameboy = test_gameboy_with(&[(0x0100, &[0x18, 0xFE]), (0x0151, &[0x11])]); // JR -2
+
+        let patch = gameboy.add_cheat("3E1-50F-EBA").unwrap();
+        gameboy.add_cheat("3E1-51F-EBA").unwrap(); // compares against 0x00, but 0x0151 holds 0x11
+        assert_eq!(gameboy.memory.read(0x0150), 0x3E);
+        assert_eq!(gameboy.memory.read(0x0151), 0x11);
+        assert!(gameboy.set_cheat_enabled(patch, false));
+        assert_eq!(gameboy.memory.read(0x0150), 0x00);
+        assert!(gameboy.remove_cheat(patch));
+        assert!(!gameboy.remove_cheat(patch));
+
+        let freeze = gameboy.add_cheat("016304C1").unwrap();
+        assert_eq!(gameboy.memory.read(0xC104), 0x00);
+        assert_eq!(gameboy.run_frame(), StopReason::FrameDone);
+        assert_eq!(gameboy.memory.read(0xC104), 0x63);
+
+        gameboy.memory.write(0xC104, 0x01);
+        gameboy.set_cheat_enabled(freeze, false);
+        assert_eq!(gameboy.run_frame(), StopReason::FrameDone);
+        assert_eq!(gameboy.memory.read(0xC104), 0x01);
+    }
+}
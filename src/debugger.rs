@@ -0,0 +1,331 @@
+// Debugger core: PC breakpoints with optional conditions, watchpoints on address ranges and
+// stepping. The run_* methods in run.rs stop on them and return the reason.
+//
+// Read and write watchpoints are checked in MappedRAM on accesses made by the CPU (instructions
+// and interrupt dispatch), not the PPU or the host peeking at memory. They trigger after the
+// accessing instruction. Breakpoints and execute watchpoints trigger before the instruction.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use crate::run::StopReason;
+use std::cell::Cell;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// Watchpoint access bits, combine with |
+pub const READ: u8 = 1;
+pub const WRITE: u8 = 2;
+pub const EXECUTE: u8 = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+    Memory(u16),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Condition {
+    pub target: Target,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Breakpoint {
+    pub id: u32,
+    pub address: u16,
+    pub condition: Option<Condition>,
+    pub bank: Option<u16>, // only break while this ROM bank is mapped at address
+    pub enabled: bool,
+    pub hits: u32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Watchpoint {
+    pub id: u32,
+    pub range: RangeInclusive<u16>,
+    pub access: u8, // READ, WRITE and/or EXECUTE
+    pub enabled: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    pub id: u32,
+    pub address: u16,
+    pub access: u8, // the single access that triggered it
+    pub value: u8, // read, written, or the opcode executed
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    next_id: u32,
+}
+
+// Lives in MappedRAM so reads, which only borrow memory, can record hits
+#[derive(Default)]
+pub struct Watches {
+    pub watchpoints: Vec<Watchpoint>,
+    pub(crate) armed: bool, // set while the CPU is accessing memory
+    hit: Cell<Option<WatchHit>>, // the first hit since the last check
+}
+
+pub fn init() -> Debugger {
+    Debugger::default()
+}
+
+// Parses a debugger number: hex, optionally prefixed with $ or 0x, or decimal prefixed with #
+pub fn parse_number(text: &str) -> Option<u16> {
+    if let Some(decimal) = text.strip_prefix('#') {
+        return decimal.parse().ok();
+    }
+    let hex = text.strip_prefix('$').or_else(|| text.strip_prefix("0x")).unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+impl Target {
+    // A register name, or an address in brackets such as [c000]
+    pub fn parse(text: &str) -> Option<Target> {
+        if let Some(address) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            return parse_number(address).map(Target::Memory);
+        }
+        let target = match text.to_ascii_lowercase().as_str() {
+            "a" => Target::A,
+            "f" => Target::F,
+            "b" => Target::B,
+            "c" => Target::C,
+            "d" => Target::D,
+            "e" => Target::E,
+            "h" => Target::H,
+            "l" => Target::L,
+            "af" => Target::AF,
+            "bc" => Target::BC,
+            "de" => Target::DE,
+            "hl" => Target::HL,
+            "sp" => Target::SP,
+            "pc" => Target::PC,
+            _ => return None,
+        };
+        Some(target)
+    }
+}
+
+impl Condition {
+    // Parses "<target> <comparison> <value>", e.g. "a == 5" or "[c000] >= $80"
+    pub fn parse(text: &str) -> Option<Condition> {
+        let mut words = text.split_whitespace();
+        let target = Target::parse(words.next()?)?;
+        let comparison = match words.next()? {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return None,
+        };
+        let value = parse_number(words.next()?)?;
+        match words.next() {
+            Some(_) => None,
+            None => Some(Condition { target, comparison, value }),
+        }
+    }
+
+    pub fn holds(&self, gameboy: &GameBoy) -> bool {
+        let registers = &gameboy.registers;
+        let actual = match self.target {
+            Target::A => registers.a as u16,
+            Target::F => registers.f as u16,
+            Target::B => registers.b as u16,
+            Target::C => registers.c as u16,
+            Target::D => registers.d as u16,
+            Target::E => registers.e as u16,
+            Target::H => registers.h as u16,
+            Target::L => registers.l as u16,
+            Target::AF => gameboy.get_af(),
+            Target::BC => gameboy.get_bc(),
+            Target::DE => gameboy.get_de(),
+            Target::HL => gameboy.get_hl(),
+            Target::SP => registers.sp,
+            Target::PC => registers.pc,
+            Target::Memory(address) => gameboy.memory.read(address) as u16,
+        };
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+        }
+    }
+}
+
+// In the syntax Condition::parse reads
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let comparison = match self.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        };
+        match self.target {
+            Target::Memory(address) => write!(f, "[${:04X}]", address)?,
+            target => write!(f, "{}", format!("{:?}", target).to_ascii_lowercase())?,
+        }
+        write!(f, " {} ${:X}", comparison, self.value)
+    }
+}
+
+impl Watches {
+    pub(crate) fn check(&self, address: u16, access: u8, value: u8) {
+        if self.hit.get().is_some() {
+            return;
+        }
+        let watchpoint = self
+            .watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.enabled && watchpoint.access & access != 0 && watchpoint.range.contains(&address));
+        if let Some(watchpoint) = watchpoint {
+            self.hit.set(Some(WatchHit { id: watchpoint.id, address, access, value }));
+        }
+    }
+
+    pub(crate) fn take_hit(&self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+}
+
+impl GameBoy {
+    pub fn add_breakpoint(&mut self, address: u16, condition: Option<Condition>) -> u32 {
+        let id = self.next_debug_id();
+        self.debugger.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+            bank: None,
+            enabled: true,
+            hits: 0,
+        });
+        id
+    }
+
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, access: u8) -> u32 {
+        let id = self.next_debug_id();
+        self.memory.watches.watchpoints.push(Watchpoint {
+            id,
+            range,
+            access,
+            enabled: true,
+        });
+        id
+    }
+
+    // Removes the breakpoint or watchpoint with this id, returning false if there isn't one
+    pub fn remove_break(&mut self, id: u32) -> bool {
+        let breakpoints = self.debugger.breakpoints.len();
+        let watchpoints = self.memory.watches.watchpoints.len();
+        self.debugger.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.memory.watches.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        breakpoints != self.debugger.breakpoints.len() || watchpoints != self.memory.watches.watchpoints.len()
+    }
+
+    pub fn set_break_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        let breakpoint = self.debugger.breakpoints.iter_mut().find(|breakpoint| breakpoint.id == id);
+        let watchpoint = self.memory.watches.watchpoints.iter_mut().find(|watchpoint| watchpoint.id == id);
+        match (breakpoint, watchpoint) {
+            (Some(breakpoint), _) => breakpoint.enabled = enabled,
+            (_, Some(watchpoint)) => watchpoint.enabled = enabled,
+            _ => return false,
+        }
+        true
+    }
+
+    fn next_debug_id(&mut self) -> u32 {
+        self.debugger.next_id += 1;
+        self.debugger.next_id
+    }
+
+    // Checked at instruction boundaries, before the instruction at PC runs
+    pub(crate) fn check_breakpoints(&mut self) -> Option<StopReason> {
+        let pc = self.registers.pc;
+        let mut triggered = false;
+        for i in 0..self.debugger.breakpoints.len() {
+            let breakpoint = &self.debugger.breakpoints[i];
+            if !breakpoint.enabled || breakpoint.address != pc {
+                continue;
+            }
+            if let (Some(bank), Some(mapped)) = (breakpoint.bank, self.memory.bank(pc)) {
+                if bank != mapped {
+                    continue;
+                }
+            }
+            let holds = match breakpoint.condition {
+                Some(condition) => condition.holds(self),
+                None => true,
+            };
+            if holds {
+                self.debugger.breakpoints[i].hits += 1;
+                triggered = true;
+            }
+        }
+        if triggered {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        self.memory.watches.check(pc, EXECUTE, self.memory.read(pc));
+        self.memory.watches.take_hit().map(StopReason::Watchpoint)
+    }
+
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_instruction()
+    }
+
+    // Runs a CALL or RST until it returns, anything else for one instruction
+    pub fn step_over(&mut self) -> StopReason {
+        let instruction = self.disassemble(self.registers.pc);
+        if !matches!(instruction.mnemonic, "call" | "rst") {
+            return self.run_instruction();
+        }
+        let return_address = instruction.next_address();
+        let sp = self.registers.sp;
+        self.run_to_boundary(|gameboy| gameboy.registers.pc == return_address && gameboy.registers.sp >= sp)
+    }
+
+    // Runs until the current routine returns to its caller
+    pub fn step_out(&mut self) -> StopReason {
+        let sp = self.registers.sp;
+        let mut last = self.disassemble(self.registers.pc);
+        self.run_to_boundary(|gameboy| {
+            let returned = matches!(last.mnemonic, "ret" | "reti")
+                && gameboy.registers.pc != last.next_address()
+                && gameboy.registers.sp > sp;
+            last = gameboy.disassemble(gameboy.registers.pc);
+            returned
+        })
+    }
+}
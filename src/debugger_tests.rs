@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod debugger_test {
+    use crate::debugger::{self, Comparison, Condition, Target, WatchHit, EXECUTE, READ, WRITE};
+    use crate::memory::Memory;
+    use crate::run::StopReason;
+    use crate::util::test_gameboy;
+
+    const CALLING_LOOP: [u8; 16] = [
+        0x21, 0x00, 0xC0, // 0100: LD HL, 0xC000
+        0xCD, 0x0A, 0x01, // 0103: CALL 0x010A
+        0x2C, // 0106: INC L
+        0x18, 0xFA, // 0107: JR -6
+        0x00, // 0109
+        0x3C, // 010A: INC A
+        0x77, // 010B: LD (HL), A
+        0x46, // 010C: LD B, (HL)
+        0x00, // 010D: NOP
+        0xC9, // 010E: RET
+        0x00,
+    ];
+
+    #[test]
+    fn conditional_breakpoint() {
+        let mut gameboy = test_gameboy(&CALLING_LOOP);
+        let condition = Condition { target: Target::A, comparison: Comparison::Equal, value: 5 };
+        let id = gameboy.add_breakpoint(0x010B, Some(condition));
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x010B));
+        assert_eq!(gameboy.registers.a, 5);
+        assert_eq!(gameboy.debugger.breakpoints[0].hits, 1);
+
+        assert!(gameboy.set_break_enabled(id, false));
+        assert_eq!(gameboy.run_cycles(2000), StopReason::CyclesElapsed);
+        assert!(gameboy.remove_break(id));
+        assert!(!gameboy.remove_break(id));
+    }
+
+    #[test]
+    fn watchpoints_trigger_on_cpu_accesses() {
+        let mut gameboy = test_gameboy(&CALLING_LOOP);
+        let id = gameboy.add_watchpoint(0xC002..=0xC003, WRITE);
+        // the host peeking doesn't count
+        gameboy.memory.read(0xC002);
+        assert_eq!(gameboy.run_frame(), StopReason::Watchpoint(WatchHit { id, address: 0xC002, access: WRITE, value: 4 }));
+        assert_eq!(gameboy.registers.pc, 0x010C); // stopped after the LD (HL), A
+
+        gameboy.remove_break(id);
+        let id = gameboy.add_watchpoint(0xC003..=0xC003, READ);
+        assert_eq!(gameboy.run_frame(), StopReason::Watchpoint(WatchHit { id, address: 0xC003, access: READ, value: 5 }));
+        assert_eq!(gameboy.registers.b, 5);
+
+        gameboy.remove_break(id);
+        let id = gameboy.add_watchpoint(0x010E..=0x010E, EXECUTE);
+        assert_eq!(gameboy.run_frame(), StopReason::Watchpoint(WatchHit { id, address: 0x010E, access: EXECUTE, value: 0xC9 }));
+        assert_eq!(gameboy.registers.pc, 0x010E); // stopped before the RET
+    }
+
+    #[test]
+    fn stepping() {
+        let mut gameboy = test_gameboy(&CALLING_LOOP);
+        assert_eq!(gameboy.step_into(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x0103);
+
+        // over the call
+        assert_eq!(gameboy.step_over(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x0106);
+        assert_eq!(gameboy.registers.a, 2);
+
+        // into the next one, then out of it
+        gameboy.step_over();
+        gameboy.step_over();
+        assert_eq!(gameboy.step_into(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x010A);
+        gameboy.step_into();
+        assert_eq!(gameboy.step_out(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x0106);
+        assert_eq!(gameboy.registers.sp, 0xFFFE);
+
+        // breakpoints inside the callee still stop a step over
+        gameboy.step_over();
+        gameboy.step_over();
+        gameboy.add_breakpoint(0x010D, None);
+        assert_eq!(gameboy.step_over(), StopReason::Breakpoint(0x010D));
+    }
+
+    #[test]
+    fn parsing_conditions() {
+        let condition = Condition::parse("[c000] >= $80").unwrap();
+        assert_eq!(condition, Condition { target: Target::Memory(0xC000), comparison: Comparison::GreaterOrEqual, value: 0x80 });
+        assert_eq!(Condition::parse(&condition.to_string()), Some(condition));
+        assert_eq!(Condition::parse("HL != 0x1234").unwrap().target, Target::HL);
+        assert_eq!(Condition::parse("a == #10").unwrap().value, 10);
+        assert_eq!(Condition::parse("a == 10 b"), None);
+        assert_eq!(Condition::parse("q == 1"), None);
+        assert_eq!(debugger::parse_number("ff40"), Some(0xFF40));
+        assert_eq!(debugger::parse_number("10000"), None);
+    }
+}
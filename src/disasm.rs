@@ -0,0 +1,332 @@
+// Disassembler, independent of execution: decode() turns the bytes at an address into an
+// Instruction, and its Display impl prints RGBDS syntax, e.g. "ld a, [hl+]" or "jr nz, $0150".
+// Cycle counts are the hardware's, in M-cycles, not the idle counts fetch_decode_execute returns.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use crate::util::*;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Register(Register),
+    Indirect(Register), // [bc], [de], [hl]
+    HlIncrement,        // [hl+]
+    HlDecrement,        // [hl-]
+    HighC,              // [c], i.e. 0xFF00 + C
+    Immediate8(u8),
+    Immediate16(u16),
+    Address(u16),     // [n16]
+    HighAddress(u8),  // 0xFF00 + n8, printed as the full address
+    Signed(i8),       // ADD SP, e8
+    SpOffset(i8),     // LD HL, SP + e8
+    Relative(u16),    // JR, printed as its target
+    Condition(Condition),
+    Bit(u8),
+    Vector(u8), // RST target
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8, // the second byte for CB prefixed instructions
+    pub prefixed: bool,
+    pub mnemonic: &'static str, // lowercase, as RGBDS writes it; "db" for illegal opcodes
+    pub operands: Vec<Operand>,
+    pub length: u8,
+    pub cycles: u8, // M-cycles, when a conditional branch isn't taken
+    pub cycles_taken: Option<u8>, // M-cycles when a conditional branch is taken
+    pub target: Option<u16>, // destination of jumps, calls and RST, when known before executing
+}
+
+const R8: [Operand; 8] = [
+    Operand::Register(Register::B),
+    Operand::Register(Register::C),
+    Operand::Register(Register::D),
+    Operand::Register(Register::E),
+    Operand::Register(Register::H),
+    Operand::Register(Register::L),
+    Operand::Indirect(Register::HL),
+    Operand::Register(Register::A),
+];
+const R16_GROUP_1: [Register; 4] = [Register::BC, Register::DE, Register::HL, Register::SP];
+const R16_GROUP_2: [Operand; 4] = [
+    Operand::Indirect(Register::BC),
+    Operand::Indirect(Register::DE),
+    Operand::HlIncrement,
+    Operand::HlDecrement,
+];
+const R16_GROUP_3: [Register; 4] = [Register::BC, Register::DE, Register::HL, Register::AF];
+const CONDITIONS: [Condition; 4] = [Condition::NZ, Condition::Z, Condition::NC, Condition::C];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const SHIFTS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+const A: Operand = Operand::Register(Register::A);
+const HL: Operand = Operand::Register(Register::HL);
+const SP: Operand = Operand::Register(Register::SP);
+
+impl Instruction {
+    fn new(address: u16, opcode: u8, mnemonic: &'static str, operands: Vec<Operand>, length: u8, cycles: u8) -> Instruction {
+        Instruction {
+            address,
+            opcode,
+            prefixed: false,
+            mnemonic,
+            operands,
+            length,
+            cycles,
+            cycles_taken: None,
+            target: None,
+        }
+    }
+
+    fn branch(mut self, cycles_taken: Option<u8>, target: Option<u16>) -> Instruction {
+        self.cycles_taken = cycles_taken;
+        self.target = target;
+        self
+    }
+
+    // Address of the instruction after this one
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length as u16)
+    }
+
+    pub fn is_illegal(&self) -> bool {
+        self.mnemonic == "db"
+    }
+}
+
+// Decodes the instruction at `address` from `bytes`, which start at that address.
+// Missing operand bytes are read as 0; callers should pass at least 3 bytes where available.
+pub fn decode(bytes: &[u8], address: u16) -> Instruction {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let n8 = byte(1);
+    let n16 = unsigned_16(byte(2), byte(1));
+    let relative = address.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+    let r8_cycles = |r8: u8, register: u8, memory: u8| match r8 {
+        6 => memory,
+        _ => register,
+    };
+    let new = |mnemonic, operands, length, cycles| Instruction::new(address, opcode, mnemonic, operands, length, cycles);
+
+    match opcode {
+        0x00 => new("nop", vec![], 1, 1),
+        0x07 => new("rlca", vec![], 1, 1),
+        0x0F => new("rrca", vec![], 1, 1),
+        0x17 => new("rla", vec![], 1, 1),
+        0x1F => new("rra", vec![], 1, 1),
+        0x27 => new("daa", vec![], 1, 1),
+        0x2F => new("cpl", vec![], 1, 1),
+        0x37 => new("scf", vec![], 1, 1),
+        0x3F => new("ccf", vec![], 1, 1),
+        0x08 => new("ld", vec![Operand::Address(n16), SP], 3, 5),
+        0x10 => new("stop", vec![], 2, 1),
+        0x18 => new("jr", vec![Operand::Relative(relative)], 2, 3).branch(None, Some(relative)),
+        0x76 => new("halt", vec![], 1, 1),
+        0xCB => decode_prefixed(address, n8),
+        0xE0 => new("ldh", vec![Operand::HighAddress(n8), A], 2, 3),
+        0xE2 => new("ldh", vec![Operand::HighC, A], 1, 2),
+        0xE8 => new("add", vec![SP, Operand::Signed(n8 as i8)], 2, 4),
+        0xEA => new("ld", vec![Operand::Address(n16), A], 3, 4),
+        0xF0 => new("ldh", vec![A, Operand::HighAddress(n8)], 2, 3),
+        0xF2 => new("ldh", vec![A, Operand::HighC], 1, 2),
+        0xF8 => new("ld", vec![HL, Operand::SpOffset(n8 as i8)], 2, 3),
+        0xF9 => new("ld", vec![SP, HL], 1, 2),
+        0xFA => new("ld", vec![A, Operand::Address(n16)], 3, 4),
+        0xCD => new("call", vec![Operand::Immediate16(n16)], 3, 6).branch(None, Some(n16)),
+        0xC3 => new("jp", vec![Operand::Immediate16(n16)], 3, 4).branch(None, Some(n16)),
+        0xC9 => new("ret", vec![], 1, 4),
+        0xD9 => new("reti", vec![], 1, 4),
+        0xE9 => new("jp", vec![HL], 1, 1),
+        0xF3 => new("di", vec![], 1, 1),
+        0xFB => new("ei", vec![], 1, 1),
+        _ => {
+            let r16 = ((opcode >> 4) & 0b11) as usize;
+            let y = ((opcode >> 3) & 0b111) as usize;
+            let z = opcode & 0b111;
+            let condition = Operand::Condition(CONDITIONS[y & 0b11]);
+            match opcode {
+                0x00..=0x3F => match opcode & 0b1111 {
+                    0b0001 => new("ld", vec![Operand::Register(R16_GROUP_1[r16]), Operand::Immediate16(n16)], 3, 3),
+                    0b0010 => new("ld", vec![R16_GROUP_2[r16], A], 1, 2),
+                    0b0011 => new("inc", vec![Operand::Register(R16_GROUP_1[r16])], 1, 2),
+                    0b1001 => new("add", vec![HL, Operand::Register(R16_GROUP_1[r16])], 1, 2),
+                    0b1010 => new("ld", vec![A, R16_GROUP_2[r16]], 1, 2),
+                    0b1011 => new("dec", vec![Operand::Register(R16_GROUP_1[r16])], 1, 2),
+                    _ => match z {
+                        0b000 => new("jr", vec![condition, Operand::Relative(relative)], 2, 2)
+                            .branch(Some(3), Some(relative)),
+                        0b100 => new("inc", vec![R8[y]], 1, r8_cycles(y as u8, 1, 3)),
+                        0b101 => new("dec", vec![R8[y]], 1, r8_cycles(y as u8, 1, 3)),
+                        _ => new("ld", vec![R8[y], Operand::Immediate8(n8)], 2, r8_cycles(y as u8, 2, 3)),
+                    },
+                },
+                0x40..=0x7F => new("ld", vec![R8[y], R8[z as usize]], 1, r8_cycles(y as u8, 1, 2).max(r8_cycles(z, 1, 2))),
+                0x80..=0xBF => new(ALU[y], vec![A, R8[z as usize]], 1, r8_cycles(z, 1, 2)),
+                _ => match z {
+                    0b000 if y < 4 => new("ret", vec![condition], 1, 2).branch(Some(5), None),
+                    0b001 if y & 1 == 0 => new("pop", vec![Operand::Register(R16_GROUP_3[r16])], 1, 3),
+                    0b010 if y < 4 => new("jp", vec![condition, Operand::Immediate16(n16)], 3, 3)
+                        .branch(Some(4), Some(n16)),
+                    0b100 if y < 4 => new("call", vec![condition, Operand::Immediate16(n16)], 3, 3)
+                        .branch(Some(6), Some(n16)),
+                    0b101 if y & 1 == 0 => new("push", vec![Operand::Register(R16_GROUP_3[r16])], 1, 4),
+                    0b110 => new(ALU[y], vec![A, Operand::Immediate8(n8)], 2, 2),
+                    0b111 => new("rst", vec![Operand::Vector(opcode & 0b00_111_000)], 1, 4)
+                        .branch(None, Some((opcode & 0b00_111_000) as u16)),
+                    _ => new("db", vec![Operand::Immediate8(opcode)], 1, 1),
+                },
+            }
+        }
+    }
+}
+
+fn decode_prefixed(address: u16, opcode: u8) -> Instruction {
+    let r8 = opcode & 0b111;
+    let y = (opcode >> 3) & 0b111;
+    let (mnemonic, operands) = match opcode >> 6 {
+        0b00 => (SHIFTS[y as usize], vec![R8[r8 as usize]]),
+        0b01 => ("bit", vec![Operand::Bit(y), R8[r8 as usize]]),
+        0b10 => ("res", vec![Operand::Bit(y), R8[r8 as usize]]),
+        _ => ("set", vec![Operand::Bit(y), R8[r8 as usize]]),
+    };
+    let cycles = match (r8, opcode >> 6) {
+        (6, 0b01) => 3,
+        (6, _) => 4,
+        _ => 2,
+    };
+    let mut instruction = Instruction::new(address, opcode, mnemonic, operands, 2, cycles);
+    instruction.prefixed = true;
+    instruction
+}
+
+// Decodes the instruction at `address` as the CPU would see it, without side effects
+pub fn decode_at(memory: &impl Memory, address: u16) -> Instruction {
+    let bytes = [0, 1, 2].map(|i| memory.read(address.wrapping_add(i)));
+    decode(&bytes, address)
+}
+
+// Decodes consecutive instructions starting at `start` until one would begin at or after `end`
+pub fn disassemble_range(memory: &impl Memory, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = start as u32;
+    while address < end as u32 {
+        let instruction = decode_at(memory, address as u16);
+        address += instruction.length as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+impl GameBoy {
+    pub fn disassemble(&self, address: u16) -> Instruction {
+        decode_at(&self.memory, address)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Register::A => "a",
+            Register::B => "b",
+            Register::C => "c",
+            Register::D => "d",
+            Register::E => "e",
+            Register::H => "h",
+            Register::L => "l",
+            Register::AF => "af",
+            Register::BC => "bc",
+            Register::DE => "de",
+            Register::HL => "hl",
+            Register::SP => "sp",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Condition::NZ => "nz",
+            Condition::Z => "z",
+            Condition::NC => "nc",
+            Condition::C => "c",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Indirect(register) => write!(f, "[{}]", register),
+            Operand::HlIncrement => f.write_str("[hl+]"),
+            Operand::HlDecrement => f.write_str("[hl-]"),
+            Operand::HighC => f.write_str("[c]"),
+            Operand::Immediate8(value) => write!(f, "${:02X}", value),
+            Operand::Immediate16(value) => write!(f, "${:04X}", value),
+            Operand::Address(address) => write!(f, "[${:04X}]", address),
+            Operand::HighAddress(offset) => write!(f, "[${:04X}]", 0xFF00 | *offset as u16),
+            Operand::Signed(offset) => write!(f, "{}", offset),
+            Operand::SpOffset(offset) if *offset < 0 => write!(f, "sp - {}", -(*offset as i16)),
+            Operand::SpOffset(offset) => write!(f, "sp + {}", offset),
+            Operand::Relative(target) => write!(f, "${:04X}", target),
+            Operand::Condition(condition) => write!(f, "{}", condition),
+            Operand::Bit(bit) => write!(f, "{}", bit),
+            Operand::Vector(vector) => write!(f, "${:02X}", vector),
+        }
+    }
+}
+
+impl Instruction {
+    // Formats like Display, but names the addresses operands refer to when `label` has a name
+    pub fn format_with(&self, label: &dyn Fn(u16) -> Option<String>) -> String {
+        let mut text = self.mnemonic.to_string();
+        for (i, operand) in self.operands.iter().enumerate() {
+            text.push_str(if i == 0 { " " } else { ", " });
+            let address = match operand {
+                Operand::Address(address) | Operand::Relative(address) => Some(*address),
+                Operand::HighAddress(offset) => Some(0xFF00 | *offset as u16),
+                Operand::Immediate16(value) if matches!(self.mnemonic, "jp" | "call") => Some(*value),
+                _ => None,
+            };
+            match (operand, address.and_then(label)) {
+                (Operand::Address(_) | Operand::HighAddress(_), Some(name)) => text.push_str(&format!("[{}]", name)),
+                (_, Some(name)) => text.push_str(&name),
+                _ => text.push_str(&operand.to_string()),
+            }
+        }
+        text
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format_with(&|_| None))
+    }
+}
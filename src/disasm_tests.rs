@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod disasm_test {
+    use crate::disasm::{self, Condition, Operand};
+    use crate::gb;
+    use crate::util::test_gameboy_with;
+
+    #[test]
+    fn formats_rgbds_syntax() {
+        let program = [
+            0x21, 0x00, 0xC0, // ld hl, $C000
+            0x2A, // ld a, [hl+]
+            0xE0, 0x44, // ldh [$FF44], a
+            0xCB, 0x7E, // bit 7, [hl]
+            0xF8, 0xFE, // ld hl, sp - 2
+            0x20, 0xF4, // jr nz, $0000
+            0xDC, 0x34, 0x12, // call c, $1234
+            0xFF, // rst $38
+            0xD3, // illegal
+        ];
+        let gameboy = test_gameboy_with(&[(0, &program)]);
+
+        let text: Vec<String> = disasm::disassemble_range(&gameboy.memory, 0, program.len() as u16)
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+        assert_eq!(text, [
+            "ld hl, $C000",
+            "ld a, [hl+]",
+            "ldh [$FF44], a",
+            "bit 7, [hl]",
+            "ld hl, sp - 2",
+            "jr nz, $0000",
+            "call c, $1234",
+            "rst $38",
+            "db $D3",
+        ]);
+    }
+
+    #[test]
+    fn decodes_branches_and_timing() {
+        let jump = disasm::decode(&[0x38, 0x10], 0x0150);
+        assert_eq!(jump.operands, vec![Operand::Condition(Condition::C), Operand::Relative(0x0162)]);
+        assert_eq!((jump.length, jump.cycles, jump.cycles_taken, jump.target), (2, 2, Some(3), Some(0x0162)));
+
+        let call = disasm::decode(&[0xCD, 0x00, 0x40], 0x0200);
+        assert_eq!((call.length, call.cycles, call.target), (3, 6, Some(0x4000)));
+        assert_eq!(call.next_address(), 0x0203);
+
+        let swap = disasm::decode(&[0xCB, 0x36], 0x0000);
+        assert!(swap.prefixed);
+        assert_eq!((swap.to_string().as_str(), swap.length, swap.cycles), ("swap [hl]", 2, 4));
+
+        assert!(disasm::decode(&[0xED], 0x0000).is_illegal());
+    }
+
+    // The decoder's lengths have to agree with how far the CPU moves PC
+    #[test]
+    fn lengths_match_execution() {
+        for opcode in 0..=0xFFu8 {
+            let instruction = disasm::decode(&[opcode, 0x00, 0xC0], 0xC000);
+            let branches = instruction.target.is_some() || instruction.cycles_taken.is_some();
+            let returns = ["ret", "reti", "jp"].contains(&instruction.mnemonic);
+            // STOP's padding byte isn't skipped by the CPU
+            if instruction.is_illegal() || branches || returns || opcode == 0x10 {
+                continue;
+            }
+            let mut gameboy = gb::init();
+            gameboy.test_mode = true;
+            gameboy.registers.pc = 0xC001;
+            gameboy.registers.sp = 0xD000;
+            gameboy.memory.main[0xC001] = 0x00;
+            gameboy.memory.main[0xC002] = 0xC0;
+            gameboy.fetch_decode_execute(opcode);
+            assert_eq!(gameboy.registers.pc, 0xC000 + instruction.length as u16, "{:#04X} {}", opcode, instruction);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{gb::GameBoy, memory::Memory, util::*};
+use crate::{callstack::FrameCause, gb::GameBoy, memory::Memory, util::*};
 
 impl GameBoy {
     pub fn fetch_decode_execute(&mut self, opcode: u8) -> Option<u8> {
@@ -387,11 +387,13 @@ impl GameBoy {
                 self.registers.pc += 1;
                 let ms_byte = self.memory.read(self.registers.pc);
                 self.registers.pc += 1;
+                let return_address = self.registers.pc;
                 self.registers.sp -= 1;
                 self.memory.write(self.registers.sp, msb(self.registers.pc));
                 self.registers.sp -= 1;
                 self.memory.write(self.registers.sp, lsb(self.registers.pc));
                 self.registers.pc = unsigned_16(ms_byte, ls_byte);
+                self.enter_frame(FrameCause::Call, return_address);
                 Some(6)
             }
 
@@ -846,11 +848,13 @@ impl GameBoy {
                         }
                     }
                     if condition {
+                        let return_address = self.registers.pc;
                         self.registers.sp -= 1;
                         self.memory.write(self.registers.sp, msb(self.registers.pc));
                         self.registers.sp -= 1;
                         self.memory.write(self.registers.sp, lsb(self.registers.pc));
                         self.registers.pc = unsigned_16(ms_byte, ls_byte);
+                        self.enter_frame(FrameCause::Call, return_address);
                         return Some(6);
                     } else {
                         return Some(3);
@@ -883,11 +887,13 @@ impl GameBoy {
                     // RST
                     self.logger.log_disassembly("RST");
                     let exp = opcode & 0b00_111_000;
+                    let return_address = self.registers.pc;
                     self.registers.sp -= 1;
                     self.memory.write(self.registers.sp, msb(self.registers.pc));
                     self.registers.sp -= 1;
                     self.memory.write(self.registers.sp, lsb(self.registers.pc));
                     self.registers.pc = unsigned_16(0x00, exp);
+                    self.enter_frame(FrameCause::Rst(exp), return_address);
                     return Some(4);
                 }
 
@@ -1,5 +1,17 @@
+use crate::callstack::{self, FrameCause};
+use crate::debugger;
 use crate::memory::{self, MappedRAM, Memory, MappingType};
 use crate::log;
+use crate::profiler;
+use crate::gbs;
+use crate::scheduler::{self, PpuEvent};
+use crate::serial;
+use crate::stats;
+use crate::symbols;
+use crate::trace;
+
+pub const CYCLES_PER_FRAME: u32 = 17556; // 154 lines of 114 M-cycles
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Registers {
     pub a: u8,
     pub f: u8,
@@ -16,6 +28,7 @@ pub struct Registers {
 
 #[derive(PartialEq)]
 #[derive(Debug)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub enum IsrState {
     ReadIF,
     ReadIE,
@@ -25,13 +38,23 @@ pub enum IsrState {
     None,
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Isr {
-    state: IsrState,
+    pub(crate) state: IsrState,
     iflag: u8,
     ienable: u8,
     ir_addr: u16,
 }
 
+impl Isr {
+    pub(crate) fn reset(&mut self) {
+        self.state = IsrState::None;
+        self.iflag = 0;
+        self.ienable = 0;
+        self.ir_addr = 0;
+    }
+}
+
 pub struct GameBoy {
     pub clock: u128, // measured in m-cycles, NOT T-cycles.
     pub running: bool,
@@ -42,10 +65,21 @@ pub struct GameBoy {
     pub ime_dispatch: Option<u8>,
     pub display_temp: [u8; 160*144], // contents of frame as ppu draws before vblank
     pub display: [u8; 160*144], // after vblank
+    pub frame_count: u64, // frames published to display
+    pub instructions: u64, // instructions fetched
+    pub debugger: debugger::Debugger,
+    pub(crate) shadow_stack: callstack::CallStack, // see call_stack()
+    pub symbols: symbols::Symbols,
     pub logger: log::Logger,
     pub isr: Isr,
-    window_line_counter: u8,
+    pub(crate) window_line_counter: u8,
+    pub(crate) scheduler: scheduler::Scheduler,
     pub test_mode: bool,
+    pub gbs: Option<gbs::GbsPlayer>,
+    pub serial: serial::Serial,
+    pub tracer: Option<trace::Tracer>,
+    pub profiler: Option<profiler::Profiler>,
+    pub(crate) stats: Option<stats::Stats>, // see stats()
 }
 
 pub fn init() -> GameBoy {
@@ -66,6 +100,17 @@ pub fn init() -> GameBoy {
         main: [0u8; memory::GB_RAM_SIZE],
         rom: [0; memory::GB_ROM_SIZE],
         mapping_type: MappingType::Default,
+        cartridge: Vec::new(),
+        rom_bank: 1,
+        mbc: memory::Mbc::None,
+        joypad: 0,
+        ly_stub: None,
+        watches: Default::default(),
+        cdl: None,
+        access_counts: None,
+        cheats: Default::default(),
+        interrupts_stale: true,
+        serial_stale: true,
     };
 
     let logger = log::Logger {
@@ -89,14 +134,49 @@ pub fn init() -> GameBoy {
         ime_dispatch: None,
         display_temp: [0; 160*144],
         display: [0; 160*144],
+        frame_count: 0,
+        instructions: 0,
+        debugger: debugger::init(),
+        shadow_stack: Default::default(),
+        symbols: Default::default(),
         logger: logger,
         isr: isr,
         window_line_counter: 0,
+        scheduler: scheduler::init(),
         test_mode: false,
+        gbs: None,
+        serial: serial::init(),
+        tracer: None,
+        profiler: None,
+        stats: None,
     }
 }
 
 impl GameBoy {
+    // True between instructions, when the next tick fetches an opcode
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.running && self.cycles_to_idle == Some(0) && self.isr.state == IsrState::None
+    }
+
+    // Puts the machine in the state the DMG boot ROM leaves it in, ready to run the cartridge at 0x100
+    pub fn skip_boot_rom(&mut self) {
+        self.registers.a = 0x01;
+        self.registers.f = 0xB0;
+        self.registers.b = 0x00;
+        self.registers.c = 0x13;
+        self.registers.d = 0x00;
+        self.registers.e = 0xD8;
+        self.registers.h = 0x01;
+        self.registers.l = 0x4D;
+        self.registers.sp = 0xFFFE;
+        self.registers.pc = 0x0100;
+        self.memory.main[0xFF40] = 0x91; // LCDC
+        self.memory.main[0xFF47] = 0xFC; // BGP
+        self.memory.main[0xFF50] = 1; // boot ROM unmapped
+        self.running = true;
+        self.cycles_to_idle = Some(0);
+    }
+
     pub fn tick(&mut self) {
         // This should be called once every M-cycle.
         // Current behaviour is M-cycle faking, i.e. all work is done in first M-cycle
@@ -106,10 +186,13 @@ impl GameBoy {
             self.update_ime(false);
             
             if self.test_mode == false {
-                self.trigger_interrupts();
+                self.update_interrupt_requests();
 
                 if self.isr.state != IsrState::None {
+                    self.memory.watches.armed = true;
                     self.handle_interrupt();
+                    self.memory.watches.armed = false;
+                    self.memory.interrupts_stale = true;
                     return;
                 } else if self.ime && ((self.get_ie() & self.get_if()) != 0) {
                     self.isr.state = IsrState::ReadIF;    
@@ -118,9 +201,25 @@ impl GameBoy {
 
             if let Some(cycles_to_idle) = self.cycles_to_idle {
                 if cycles_to_idle == 0 {
+                    if self.tracer.is_some() {
+                        self.trace_instruction();
+                    }
+                    if self.memory.cdl.is_some() {
+                        self.log_code();
+                    }
+                    if self.profiler.is_some() {
+                        self.profile_instruction();
+                    }
                     let opcode: u8 = self.memory.read(self.registers.pc);
                     self.registers.pc += 1;
+                    self.instructions += 1;
+                    if self.stats.is_some() {
+                        self.count_instruction(opcode);
+                    }
+                    self.memory.watches.armed = true;
                     self.cycles_to_idle = self.fetch_decode_execute(opcode);
+                    self.memory.watches.armed = false;
+                    self.memory.interrupts_stale = true;
                 } else {   
                     self.cycles_to_idle = Some(self.cycles_to_idle.unwrap() - 1);
                 }
@@ -128,11 +227,29 @@ impl GameBoy {
 
             self.update_ime(true);
 
+            self.gbs_tick();
+
             if self.test_mode == false { 
-                self.renderer() 
+                if self.serial_needs_polling() {
+                    self.serial_tick();
+                    self.memory.serial_stale = true; // the transfer's progress decides the next poll
+                }
+                self.run_due_events();
             };
             
             self.clock += 1;
+        } else if let Some(stats) = self.stats.as_mut() {
+            stats.halt_ticks += 1;
+        }
+    }
+
+    // Raises the interrupts that LY, LYC and STAT call for. Those only change when an instruction
+    // or the ISR runs, the scheduler handles an event or one of the registers is written, so in
+    // between there's nothing to redo.
+    pub(crate) fn update_interrupt_requests(&mut self) {
+        if self.memory.interrupts_stale {
+            self.trigger_interrupts();
+            self.memory.interrupts_stale = false; // after its own writes to STAT and IF
         }
     }
 
@@ -196,7 +313,10 @@ impl GameBoy {
             }
             IsrState::Jump => {
                 self.logger.log_info("ISR: Jump");
+                let return_address = self.registers.pc;
                 self.registers.pc = self.isr.ir_addr;
+                self.enter_frame(FrameCause::Interrupt(self.isr.ir_addr), return_address);
+                self.count_interrupt(self.isr.ir_addr);
                 self.logger.log_info(&format!("ISR: Jumping to: {:#x}", self.isr.ir_addr));
                 self.isr.state = IsrState::None;
                 self.isr.ir_addr = 0;
@@ -222,32 +342,40 @@ impl GameBoy {
         }
     }
     
-    fn renderer(&mut self) {
-        if self.clock % 114 == 0 {
-            if self.get_ly() == 144 { // VBlank entered
-                self.window_line_counter = 0;
-                self.set_stat(self.get_stat() & 0b11111101);
-                self.set_if(self.get_if() | 1);
-                self.logger.log_info("Renderer: Entered VBlank");
-            } else {
-                self.set_stat(self.get_stat() & 0b11111110);
-            
+    pub(crate) fn renderer(&mut self, event: PpuEvent) {
+        match event {
+            PpuEvent::LineStart => {
+                if self.get_ly() == 144 { // VBlank entered
+                    self.window_line_counter = 0;
+                    self.set_stat(self.get_stat() & 0b11111101);
+                    self.set_if(self.get_if() | 1);
+                    self.logger.log_info("Renderer: Entered VBlank");
+                    if !self.memory.cheats.is_empty() {
+                        self.apply_gameshark_codes();
+                    }
+                } else {
+                    self.set_stat(self.get_stat() & 0b11111110);
+                
+                }
             }
-        }
-        else if self.clock % 114 == 20 {
-            if self.get_ly() < 144 { //Drawing
-                self.render_scanline();
-                self.set_stat(self.get_stat() & 0b11111111);
-            
+            PpuEvent::Draw => {
+                if self.get_ly() < 144 { //Drawing
+                    self.render_scanline();
+                    self.set_stat(self.get_stat() & 0b11111111);
+                
+                }
+            }
+            PpuEvent::HBlank => {
+                // HBlank
+                self.set_stat(self.get_stat() & 0b11111100);
+                self.set_ly(self.get_ly() + 1);
             }
-        } else if self.clock % 114 == 63 {
-            // HBlank
-            self.set_stat(self.get_stat() & 0b11111100);
-            self.set_ly(self.get_ly() + 1);
-        } else if self.clock % 114 == 113 {
-            if self.get_ly() == 154 { // VBlank exited
-                self.display = self.display_temp;
-                self.set_ly(0);
+            PpuEvent::LineEnd => {
+                if self.get_ly() == 154 { // VBlank exited
+                    self.display = self.display_temp;
+                    self.frame_count += 1;
+                    self.set_ly(0);
+                }
             }
         }
     }
@@ -336,7 +464,7 @@ impl GameBoy {
         }
     }
     fn get_ly(&self) -> u8 {
-        self.memory.read(0xFF44)
+        self.memory.main[0xFF44] // the PPU sees the real LY even when it's stubbed for the CPU
     }
 
     fn set_ly(&mut self, data: u8) {
@@ -367,14 +495,14 @@ impl GameBoy {
         (self.get_background_palette() >> (data<<1)) & 0b11
     }
 
-    fn get_ie(&self) -> u8 {
+    pub(crate) fn get_ie(&self) -> u8 {
         self.memory.read(0xFFFF)
     }
 
-    fn get_if(&self) -> u8 {
+    pub(crate) fn get_if(&self) -> u8 {
         self.memory.read(0xFF0F)
     }
-    fn set_if(&mut self, data: u8) {
+    pub(crate) fn set_if(&mut self, data: u8) {
         self.memory.write(0xFF0F, data);
     }
     
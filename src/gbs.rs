@@ -0,0 +1,245 @@
+// GBS (Game Boy Sound) music rips.
+// A GBS file is a 0x70 byte header followed by the music driver's code and data,
+// which gets loaded into the cartridge area at the header's load address.
+// https://ocremix.org/info/GBS_Format_Specification
+//
+// Interrupts stay off while a rip plays. Instead play is called at a fixed rate: once a frame,
+// or as often as the timer would overflow with the header's TMA and TAC. That's an approximation:
+// rips that reprogram the timer themselves keep being called at the header's rate.
+use crate::gb::{GameBoy, CYCLES_PER_FRAME};
+use crate::memory::{self, Memory};
+use crate::util::*;
+
+pub const GBS_HEADER_SIZE: usize = 0x70;
+
+// Address of the `JR -2` loop the CPU spins in between calls to init/play.
+// GBS load addresses are at least 0x400, so this is never overwritten by the rip.
+pub const GBS_IDLE_ADDR: u16 = 0x0100;
+
+#[derive(Debug, PartialEq)]
+pub enum GbsError {
+    TooShort,
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadLoadAddress(u16),
+    NoSuchSong(u8),
+    NotLoaded, // no GBS file has been loaded
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlayTrigger {
+    VBlank,
+    Timer { tma: u8, tac: u8 },
+}
+
+#[derive(Clone)]
+pub struct Gbs {
+    pub version: u8,
+    pub song_count: u8,
+    pub first_song: u8, // 1-based, as stored in the header
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub stack_pointer: u16,
+    pub timer_modulo: u8,
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+    pub data: Vec<u8>,
+}
+
+pub struct GbsPlayer {
+    pub header: Gbs,
+    pub song: u8, // 0-based
+    pub play_period: u32, // M-cycles between calls to play
+    cycles_to_play: u32,
+}
+
+// Playback position for save states, the rip itself has to be loaded already
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct GbsState {
+    song: u8,
+    cycles_to_play: u32,
+}
+
+impl GbsPlayer {
+    pub(crate) fn save_state(&self) -> GbsState {
+        GbsState {
+            song: self.song,
+            cycles_to_play: self.cycles_to_play,
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: GbsState) {
+        self.song = state.song;
+        self.cycles_to_play = state.cycles_to_play;
+    }
+
+    // Counts down cycles the CPU skipped while idle
+    pub(crate) fn skip_cycles(&mut self, cycles: u32) {
+        self.cycles_to_play = self.cycles_to_play.saturating_sub(cycles);
+    }
+}
+
+fn header_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Gbs, GbsError> {
+    if bytes.len() < GBS_HEADER_SIZE {
+        return Err(GbsError::TooShort);
+    }
+    if &bytes[0..3] != b"GBS" {
+        return Err(GbsError::BadMagic);
+    }
+    if bytes[3] != 1 {
+        return Err(GbsError::UnsupportedVersion(bytes[3]));
+    }
+    let load_address = unsigned_16(bytes[0x07], bytes[0x06]);
+    if !(0x0400..=0x7FFF).contains(&load_address) {
+        return Err(GbsError::BadLoadAddress(load_address));
+    }
+    Ok(Gbs {
+        version: bytes[3],
+        song_count: bytes[0x04],
+        first_song: bytes[0x05],
+        load_address,
+        init_address: unsigned_16(bytes[0x09], bytes[0x08]),
+        play_address: unsigned_16(bytes[0x0B], bytes[0x0A]),
+        stack_pointer: unsigned_16(bytes[0x0D], bytes[0x0C]),
+        timer_modulo: bytes[0x0E],
+        timer_control: bytes[0x0F],
+        title: header_string(&bytes[0x10..0x30]),
+        author: header_string(&bytes[0x30..0x50]),
+        copyright: header_string(&bytes[0x50..0x70]),
+        data: bytes[GBS_HEADER_SIZE..].to_vec(),
+    })
+}
+
+impl Gbs {
+    pub fn play_trigger(&self) -> PlayTrigger {
+        if self.timer_control & 0b100 != 0 {
+            PlayTrigger::Timer {
+                tma: self.timer_modulo,
+                tac: self.timer_control,
+            }
+        } else {
+            PlayTrigger::VBlank
+        }
+    }
+
+    pub fn play_period(&self) -> u32 {
+        match self.play_trigger() {
+            PlayTrigger::VBlank => CYCLES_PER_FRAME,
+            PlayTrigger::Timer { tma, tac } => {
+                // M-cycles per TIMA increment for each TAC clock select
+                let divider = [256, 4, 16, 64][(tac & 0b11) as usize];
+                (256 - tma as u32) * divider
+            }
+        }
+    }
+
+    // Cartridge image with the rip at its load address. RST vectors are redirected to
+    // load address + vector as the spec requires, and interrupt vectors just return.
+    fn rom_image(&self) -> Vec<u8> {
+        let mut image = vec![0u8; self.load_address as usize];
+        for vector in (0..0x40).step_by(8) {
+            let target = self.load_address + vector as u16;
+            image[vector] = 0xC3; // JP u16
+            image[vector + 1] = lsb(target);
+            image[vector + 2] = msb(target);
+        }
+        for vector in (0x40..=0x60).step_by(8) {
+            image[vector] = 0xD9; // RETI
+        }
+        image[GBS_IDLE_ADDR as usize] = 0x18; // JR -2
+        image[GBS_IDLE_ADDR as usize + 1] = 0xFE;
+        image.extend_from_slice(&self.data);
+        image
+    }
+}
+
+impl GameBoy {
+    pub fn load_gbs(&mut self, gbs: Gbs) -> Result<(), GbsError> {
+        let song = gbs.first_song.max(1) - 1;
+        self.memory.load_cartridge(gbs.rom_image());
+        self.memory.mbc = memory::Mbc::Gbs;
+        self.gbs = Some(GbsPlayer {
+            play_period: gbs.play_period(),
+            cycles_to_play: 0,
+            header: gbs,
+            song: 0,
+        });
+        self.select_gbs_song(song)
+    }
+
+    // Restarts playback from `song` (0-based), calling the rip's init routine with A = song.
+    pub fn select_gbs_song(&mut self, song: u8) -> Result<(), GbsError> {
+        let player = match self.gbs.as_mut() {
+            Some(player) => player,
+            None => return Err(GbsError::NotLoaded),
+        };
+        if song >= player.header.song_count {
+            return Err(GbsError::NoSuchSong(song));
+        }
+        player.song = song;
+        player.cycles_to_play = player.play_period;
+        let init_address = player.header.init_address;
+        let stack_pointer = player.header.stack_pointer;
+        let (tma, tac) = (player.header.timer_modulo, player.header.timer_control);
+
+        for address in 0x8000..memory::GB_RAM_SIZE {
+            self.memory.main[address] = 0;
+        }
+        self.memory.interrupts_stale = true; // cleared IF, STAT, LY and LYC
+        self.memory.serial_stale = true;
+        self.memory.main[0xFF50] = 1; // boot ROM unmapped
+        self.memory.main[0xFF06] = tma;
+        self.memory.main[0xFF07] = tac;
+        self.memory.main[0xFF26] = 0x80; // sound on
+        self.memory.main[0xFF25] = 0xFF;
+        self.memory.main[0xFF24] = 0x77;
+        self.memory.rom_bank = 1;
+
+        // play is driven by the player, so interrupts stay off
+        self.ime = false;
+        self.ime_dispatch = None;
+        self.isr.reset();
+
+        self.registers.a = song;
+        self.registers.sp = stack_pointer;
+        self.gbs_call(init_address);
+        self.running = true;
+        self.cycles_to_idle = Some(0);
+        Ok(())
+    }
+
+    pub(crate) fn gbs_tick(&mut self) {
+        let player = match self.gbs.as_mut() {
+            Some(player) => player,
+            None => return,
+        };
+        if player.cycles_to_play > 0 {
+            player.cycles_to_play -= 1;
+        }
+        // Only call play once the previous call has returned to the idle loop
+        if player.cycles_to_play == 0
+            && self.registers.pc == GBS_IDLE_ADDR
+            && self.cycles_to_idle == Some(0)
+        {
+            player.cycles_to_play = player.play_period;
+            let play_address = player.header.play_address;
+            self.gbs_call(play_address);
+        }
+    }
+
+    fn gbs_call(&mut self, address: u16) {
+        self.registers.sp -= 1;
+        self.memory.write(self.registers.sp, msb(GBS_IDLE_ADDR));
+        self.registers.sp -= 1;
+        self.memory.write(self.registers.sp, lsb(GBS_IDLE_ADDR));
+        self.registers.pc = address;
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod gbs_test {
+    use crate::gb;
+    use crate::gbs::{self, GbsError, PlayTrigger};
+    use crate::memory::Memory;
+
+    // init: LD (0xC001), A; LD A, 0; LD (0xC000), A; RET
+    // play: LD A, (0xC000); INC A; LD (0xC000), A; RET
+    const DRIVER: [u8; 16] = [
+        0xEA, 0x01, 0xC0, 0x3E, 0x00, 0xEA, 0x00, 0xC0, 0xC9,
+        0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0,
+    ];
+
+    fn build_gbs(tma: u8, tac: u8) -> Vec<u8> {
+        let mut bytes = vec![0u8; gbs::GBS_HEADER_SIZE];
+        bytes[0..3].copy_from_slice(b"GBS");
+        bytes[0x03] = 1;
+        bytes[0x04] = 3; // songs
+        bytes[0x05] = 2; // first song
+        bytes[0x06..0x08].copy_from_slice(&[0x00, 0x04]); // load
+        bytes[0x08..0x0A].copy_from_slice(&[0x00, 0x04]); // init
+        bytes[0x0A..0x0C].copy_from_slice(&[0x09, 0x04]); // play
+        bytes[0x0C..0x0E].copy_from_slice(&[0xFE, 0xFF]); // sp
+        bytes[0x0E] = tma;
+        bytes[0x0F] = tac;
+        bytes[0x10..0x14].copy_from_slice(b"Test");
+        bytes.extend_from_slice(&DRIVER);
+        bytes.push(0xC9);
+        bytes
+    }
+
+    #[test]
+    fn parses_header() {
+        let gbs = gbs::parse(&build_gbs(0, 0)).expect("Could not parse GBS");
+        assert_eq!(gbs.song_count, 3);
+        assert_eq!(gbs.load_address, 0x0400);
+        assert_eq!(gbs.play_address, 0x0409);
+        assert_eq!(gbs.title, "Test");
+        assert_eq!(gbs.play_trigger(), PlayTrigger::VBlank);
+        assert_eq!(gbs::parse(b"GBX").err(), Some(GbsError::TooShort));
+    }
+
+    #[test]
+    fn vblank_trigger_calls_play_once_per_frame() {
+        let mut gameboy = gb::init();
+        gameboy.load_gbs(gbs::parse(&build_gbs(0, 0)).unwrap()).unwrap();
+        for _ in 0..(10 * 17556 + 100) {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.memory.read(0xC001), 1, "init called with first song");
+        assert_eq!(gameboy.memory.read(0xC000), 10);
+    }
+
+    #[test]
+    fn timer_trigger_and_song_select() {
+        let mut gameboy = gb::init();
+        assert_eq!(gameboy.select_gbs_song(0), Err(GbsError::NotLoaded));
+        // 262144 Hz timer reloading from 0xC0: play every 256 M-cycles
+        gameboy.load_gbs(gbs::parse(&build_gbs(0xC0, 0b101)).unwrap()).unwrap();
+        assert_eq!(gameboy.gbs.as_ref().unwrap().play_period, 256);
+        assert_eq!(gameboy.select_gbs_song(3), Err(GbsError::NoSuchSong(3)));
+        gameboy.select_gbs_song(2).unwrap();
+        for _ in 0..(4 * 256 + 100) {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.memory.read(0xC001), 2);
+        assert_eq!(gameboy.memory.read(0xC000), 4);
+    }
+
+    #[test]
+    fn bank_select_follows_the_cartridge() {
+        let mut gameboy = gb::init();
+        gameboy.load_gbs(gbs::parse(&build_gbs(0, 0)).unwrap()).unwrap();
+        gameboy.memory.write(0x2000, 0x25); // past MBC1's 5 bits
+        assert_eq!(gameboy.memory.rom_bank, 0x25);
+        gameboy.memory.write(0x2000, 0x00);
+        assert_eq!(gameboy.memory.rom_bank, 0);
+
+        let mut rom = vec![0u8; 0x10000];
+        gameboy.memory.load_cartridge(rom.clone()); // ROM only
+        gameboy.memory.write(0x2000, 0x02);
+        assert_eq!(gameboy.memory.rom_bank, 1);
+
+        rom[0x0147] = 0x01; // MBC1
+        gameboy.memory.load_cartridge(rom);
+        gameboy.memory.write(0x2000, 0x22);
+        assert_eq!(gameboy.memory.rom_bank, 0x02);
+        gameboy.memory.write(0x3FFF, 0x20);
+        assert_eq!(gameboy.memory.rom_bank, 1);
+    }
+}
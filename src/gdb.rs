@@ -0,0 +1,372 @@
+// GDB remote serial protocol server, so GDB and frontends speaking RSP can debug a GameBoy.
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//
+// GDB has no SM83 architecture, so the registers are described by target.xml: a, f, b, c, d,
+// e, h and l (8 bit) then sp and pc (16 bit, little-endian on the wire), numbered 0 to 9.
+// Memory goes through the Memory trait, so the client sees what the CPU sees. Breakpoints (Z0/Z1)
+// and watchpoints (Z2-Z4) map onto the debugger's; stepping is by instruction.
+use crate::debugger::{READ, WRITE};
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use crate::run::StopReason;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.dmg.sm83.core">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="f" bitsize="8"/>
+    <reg name="b" bitsize="8"/>
+    <reg name="c" bitsize="8"/>
+    <reg name="d" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+    <reg name="h" bitsize="8"/>
+    <reg name="l" bitsize="8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const INTERRUPT: u8 = 0x03; // sent by the client to stop a continue
+const POLL_INTERVAL: u32 = 4096; // M-cycles between checks for an interrupt while running
+
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+struct Session {
+    stream: TcpStream,
+    ack: bool,
+    breaks: HashMap<(u8, u16), u32>, // (Z type, address) to debugger id
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    (0..text.len() / 2).map(|i| u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()).collect()
+}
+
+// "addr,length" as used by m, M and Z packets
+fn parse_range(text: &str) -> Option<(u16, u32)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_hex(address)? as u16, parse_hex(length)?))
+}
+
+impl GdbServer {
+    // Listens for a debugger, e.g. on "127.0.0.1:2345"
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<GdbServer> {
+        Ok(GdbServer {
+            listener: TcpListener::bind(address)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Waits for a client and serves it until it detaches, kills the target or disconnects.
+    // The Game Boy only runs when the client continues or steps it.
+    pub fn serve(&self, gameboy: &mut GameBoy) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut session = Session {
+            stream,
+            ack: true,
+            breaks: HashMap::new(),
+        };
+        let result = session.run(gameboy);
+        for id in session.breaks.values() {
+            gameboy.remove_break(*id);
+        }
+        match result {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl Session {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    // Reads packets until a complete one arrives, returning None for a stray interrupt
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                b'$' => break,
+                INTERRUPT => return Ok(None),
+                _ => (), // acks and noise between packets
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte()? {
+                b'#' => break,
+                b'}' => data.push(self.read_byte()? ^ 0x20),
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte()?, self.read_byte()?];
+        let expected = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let valid = std::str::from_utf8(&checksum).ok().and_then(parse_hex) == Some(expected as u32);
+        if self.ack {
+            self.stream.write_all(if valid { b"+" } else { b"-" })?;
+        }
+        match valid {
+            true => Ok(Some(String::from_utf8_lossy(&data).into_owned())),
+            false => self.read_packet(),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let packet = format!("${}#{:02x}", data, checksum);
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if !self.ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn run(&mut self, gameboy: &mut GameBoy) -> io::Result<()> {
+        loop {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => continue, // interrupt while already stopped
+            };
+            let reply = match packet.as_bytes().first() {
+                Some(b'?') => format!("S{:02x}", SIGTRAP),
+                Some(b'g') => hex(&registers(gameboy)),
+                Some(b'G') => match parse_bytes(&packet[1..]) {
+                    Some(bytes) if bytes.len() == 12 => {
+                        for (register, value) in [0, 1, 2, 3, 4, 5, 6, 7].into_iter().zip(&bytes[..8]) {
+                            set_register(gameboy, register, *value as u16);
+                        }
+                        set_register(gameboy, 8, u16::from_le_bytes([bytes[8], bytes[9]]));
+                        set_register(gameboy, 9, u16::from_le_bytes([bytes[10], bytes[11]]));
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                },
+                Some(b'p') => match parse_hex(&packet[1..]) {
+                    Some(register @ 0..=7) => hex(&registers(gameboy)[register as usize..register as usize + 1]),
+                    Some(8) => hex(&registers(gameboy)[8..10]),
+                    Some(9) => hex(&registers(gameboy)[10..12]),
+                    _ => "E01".to_string(),
+                },
+                Some(b'P') => {
+                    let parsed = packet[1..].split_once('=').and_then(|(register, value)| {
+                        let bytes = parse_bytes(value)?;
+                        let value = match bytes.len() {
+                            1 => bytes[0] as u16,
+                            2 => u16::from_le_bytes([bytes[0], bytes[1]]),
+                            _ => return None,
+                        };
+                        Some((parse_hex(register)?, value))
+                    });
+                    match parsed {
+                        Some((register, value)) if register <= 9 => {
+                            set_register(gameboy, register, value);
+                            "OK".to_string()
+                        }
+                        _ => "E01".to_string(),
+                    }
+                }
+                Some(b'm') => match parse_range(&packet[1..]) {
+                    Some((address, length)) => {
+                        let bytes: Vec<u8> = (0..length.min(0x10000))
+                            .map(|i| gameboy.memory.read(address.wrapping_add(i as u16)))
+                            .collect();
+                        hex(&bytes)
+                    }
+                    None => "E01".to_string(),
+                },
+                Some(b'M') => {
+                    let parsed = packet[1..]
+                        .split_once(':')
+                        .and_then(|(range, data)| Some((parse_range(range)?, parse_bytes(data)?)));
+                    match parsed {
+                        Some(((address, _), bytes)) => {
+                            for (i, byte) in bytes.iter().enumerate() {
+                                gameboy.memory.write(address.wrapping_add(i as u16), *byte);
+                            }
+                            "OK".to_string()
+                        }
+                        None => "E01".to_string(),
+                    }
+                }
+                Some(b'Z') | Some(b'z') => self.set_break(gameboy, &packet),
+                Some(b's') => self.stop_reply(gameboy.step_into()),
+                Some(b'c') => {
+                    let reason = self.resume(gameboy)?;
+                    self.stop_reply(reason)
+                }
+                Some(b'H') => "OK".to_string(),
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'q') | Some(b'Q') => self.query(&packet),
+                _ => String::new(), // unsupported, including vCont so the client falls back to s and c
+            };
+            self.send(&reply)?;
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if packet == "QStartNoAckMode" {
+            // acknowledged before switching, as the reply still gets an ack
+            self.ack = false;
+            return "OK".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_range(range) {
+                Some((offset, length)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = (start + length as usize).min(TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    format!("{}{}", more, &TARGET_XML[start..end])
+                }
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // Z/z type,addr,kind: 0 and 1 are breakpoints, 2 write, 3 read and 4 access watchpoints
+    fn set_break(&mut self, gameboy: &mut GameBoy, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+        let parsed = packet[1..].split_once(',').and_then(|(kind, range)| Some((parse_hex(kind)?, parse_range(range)?)));
+        let (kind, (address, length)) = match parsed {
+            Some((kind, range)) if kind <= 4 => (kind as u8, range),
+            Some(_) => return String::new(),
+            None => return "E01".to_string(),
+        };
+        let key = (kind, address);
+        if !insert {
+            if let Some(id) = self.breaks.remove(&key) {
+                gameboy.remove_break(id);
+            }
+            return "OK".to_string();
+        }
+        if self.breaks.contains_key(&key) {
+            return "OK".to_string();
+        }
+        let end = address.saturating_add(length.max(1) as u16 - 1);
+        let id = match kind {
+            0 | 1 => gameboy.add_breakpoint(address, None),
+            2 => gameboy.add_watchpoint(address..=end, WRITE),
+            3 => gameboy.add_watchpoint(address..=end, READ),
+            _ => gameboy.add_watchpoint(address..=end, READ | WRITE),
+        };
+        self.breaks.insert(key, id);
+        "OK".to_string()
+    }
+
+    // Runs until something stops the Game Boy or the client interrupts it
+    fn resume(&mut self, gameboy: &mut GameBoy) -> io::Result<Option<StopReason>> {
+        self.stream.set_nonblocking(true)?;
+        let mut cycles = 0;
+        let mut interrupted = false;
+        let mut error = None;
+        let stream = &mut self.stream;
+        let reason = gameboy.run_until(|_| {
+            cycles += 1;
+            if cycles % POLL_INTERVAL != 0 {
+                return false;
+            }
+            let mut byte = [0u8];
+            match stream.read(&mut byte) {
+                Ok(0) => error = Some(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(_) => interrupted = byte[0] == INTERRUPT,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => error = Some(e),
+            }
+            interrupted || error.is_some()
+        });
+        self.stream.set_nonblocking(false)?;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        Ok(match interrupted {
+            true => None,
+            false => Some(reason),
+        })
+    }
+
+    // None is a stop requested by the client
+    fn stop_reply(&self, reason: impl Into<Option<StopReason>>) -> String {
+        match reason.into() {
+            None => format!("S{:02x}", SIGINT),
+            Some(StopReason::IllegalOpcode(_)) => format!("S{:02x}", SIGILL),
+            Some(StopReason::Watchpoint(hit)) => {
+                // A Z4 access watchpoint is reported as one whichever way it was accessed
+                let z_type = self.breaks.iter().find(|(_, id)| **id == hit.id).map(|((kind, _), _)| *kind);
+                let kind = match (z_type, hit.access) {
+                    (Some(4), _) => "awatch",
+                    (_, WRITE) => "watch",
+                    (_, READ) => "rwatch",
+                    _ => return format!("S{:02x}", SIGTRAP),
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.address)
+            }
+            Some(_) => format!("S{:02x}", SIGTRAP),
+        }
+    }
+}
+
+// The registers in target.xml order, as sent by g
+fn registers(gameboy: &GameBoy) -> [u8; 12] {
+    let r = &gameboy.registers;
+    let sp = r.sp.to_le_bytes();
+    let pc = r.pc.to_le_bytes();
+    [r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l, sp[0], sp[1], pc[0], pc[1]]
+}
+
+fn set_register(gameboy: &mut GameBoy, register: u32, value: u16) {
+    let r = &mut gameboy.registers;
+    match register {
+        0 => r.a = value as u8,
+        1 => r.f = value as u8 & 0xF0,
+        2 => r.b = value as u8,
+        3 => r.c = value as u8,
+        4 => r.d = value as u8,
+        5 => r.e = value as u8,
+        6 => r.h = value as u8,
+        7 => r.l = value as u8,
+        8 => r.sp = value,
+        _ => r.pc = value,
+    }
+}
+
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod gdb_test {
+    use crate::gdb::GdbServer;
+    use crate::memory::Memory;
+    use crate::util::test_gameboy;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    // Sends a packet and returns the reply, acking both ways
+    fn command(stream: &mut TcpStream, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(stream, "${}#{:02x}", data, checksum).unwrap();
+        let mut reply = Vec::new();
+        let mut byte = [0u8];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' => continue,
+                b'$' => break,
+                other => panic!("unexpected {}", other as char),
+            }
+        }
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        stream.read_exact(&mut [0u8; 2]).unwrap();
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let program = [
+            0x21, 0x00, 0xC0, // 0100: LD HL, 0xC000
+            0x3C, // 0103: INC A
+            0x77, // 0104: LD (HL), A
+            0x46, // 0105: LD B, (HL)
+            0x18, 0xFB, // 0106: JR -5
+        ];
+        let mut gameboy = test_gameboy(&program);
+
+        let server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            assert!(command(&mut stream, "qSupported:swbreak+").contains("qXfer:features:read+"));
+            assert!(command(&mut stream, "qXfer:features:read:target.xml:0,fff").starts_with("l<?xml"));
+            assert_eq!(command(&mut stream, "?"), "S05");
+            assert!(command(&mut stream, "g").ends_with("feff0001")); // SP=FFFE, PC=0100
+            assert_eq!(command(&mut stream, "m100,3"), "2100c0");
+
+            assert_eq!(command(&mut stream, "Z0,104,1"), "OK");
+            assert_eq!(command(&mut stream, "c"), "S05");
+            assert_eq!(command(&mut stream, "p9"), "0401");
+            assert_eq!(command(&mut stream, "z0,104,1"), "OK");
+            assert_eq!(command(&mut stream, "s"), "S05");
+            assert_eq!(command(&mut stream, "p9"), "0501");
+
+            assert_eq!(command(&mut stream, "Z2,c000,1"), "OK");
+            assert_eq!(command(&mut stream, "c"), "T05watch:c000;");
+            assert_eq!(command(&mut stream, "z2,c000,1"), "OK");
+            assert_eq!(command(&mut stream, "Z4,c000,1"), "OK");
+            assert_eq!(command(&mut stream, "c"), "T05awatch:c000;"); // a read
+            assert_eq!(command(&mut stream, "z4,c000,1"), "OK");
+
+            assert_eq!(command(&mut stream, "P0=2a"), "OK");
+            assert_eq!(command(&mut stream, "p0"), "2a");
+            assert_eq!(command(&mut stream, "Mc001,2:beef"), "OK");
+            assert_eq!(command(&mut stream, "mc001,2"), "beef");
+            assert_eq!(command(&mut stream, "D"), "OK");
+        });
+        server.serve(&mut gameboy).unwrap();
+        client.join().unwrap();
+
+        assert_eq!(gameboy.registers.a, 0x2A);
+        assert_eq!(gameboy.memory.read(0xC002), 0xEF);
+        assert!(gameboy.debugger.breakpoints.is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+// Joypad buttons as bits of MappedRAM::joypad, 1 = pressed.
+// The low nibble is the d-pad and the high nibble the buttons, in P1 (0xFF00) line order.
+use crate::gb::GameBoy;
+
+pub const RIGHT: u8 = 0x01;
+pub const LEFT: u8 = 0x02;
+pub const UP: u8 = 0x04;
+pub const DOWN: u8 = 0x08;
+pub const A: u8 = 0x10;
+pub const B: u8 = 0x20;
+pub const SELECT: u8 = 0x40;
+pub const START: u8 = 0x80;
+
+impl GameBoy {
+    // Replaces the pressed buttons, requesting the joypad interrupt if any were newly pressed
+    pub fn set_buttons(&mut self, buttons: u8) {
+        let newly_pressed = buttons & !self.memory.joypad;
+        self.memory.joypad = buttons;
+        if newly_pressed != 0 {
+            self.set_if(self.get_if() | 0b10000);
+        }
+    }
+
+    pub fn press(&mut self, buttons: u8) {
+        self.set_buttons(self.memory.joypad | buttons);
+    }
+
+    pub fn release(&mut self, buttons: u8) {
+        self.set_buttons(self.memory.joypad & !buttons);
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.memory.joypad
+    }
+}
@@ -1,8 +1,52 @@
 #![feature(custom_test_frameworks)]
 #![test_runner(datatest::runner)]
+pub mod bess;
+pub mod bess_tests;
+pub mod blargg_tests;
+pub mod callstack;
+pub mod callstack_tests;
+pub mod cdl;
+pub mod cdl_tests;
+pub mod cheats;
+pub mod cheats_tests;
+pub mod debugger;
+pub mod debugger_tests;
+pub mod disasm;
+pub mod disasm_tests;
 pub mod fde;
 pub mod gb;
+pub mod gbs;
+pub mod gbs_tests;
+pub mod gdb;
+pub mod gdb_tests;
+pub mod joypad;
+pub mod link;
 pub mod memory;
+pub mod movie;
+pub mod movie_tests;
+pub mod patch;
+pub mod patch_tests;
+pub mod printer;
+pub mod profiler;
+pub mod profiler_tests;
+pub mod rewind;
+pub mod rewind_tests;
+pub mod run;
+pub mod run_tests;
+pub mod scheduler;
+pub mod search;
+pub mod search_tests;
+pub mod serial;
+pub mod serial_tests;
 pub mod single_step_tests;
+pub mod socket_link;
+pub mod state;
+pub mod state_tests;
+pub mod stats;
+pub mod stats_tests;
+pub mod symbols;
+pub mod symbols_tests;
+pub mod trace;
+pub mod trace_tests;
 pub mod util;
 mod log;
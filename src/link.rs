@@ -0,0 +1,85 @@
+// Virtual link cable between two Game Boys stepped in lockstep in the same process.
+// Whichever side sets SC bit 0 drives the clock; the other side only shifts when it has
+// a transfer enabled on the external clock, otherwise the master reads 1s.
+use crate::gb::GameBoy;
+use crate::serial::SerialDevice;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Default)]
+struct Wire {
+    ready: [Option<bool>; 2], // bit each side would shift out on an external clock pulse
+    pending: [Option<bool>; 2], // bit clocked in by the master, not yet shifted in by this side
+}
+
+pub struct LinkPort {
+    wire: Rc<RefCell<Wire>>,
+    side: usize,
+}
+
+impl SerialDevice for LinkPort {
+    fn exchange_bit(&mut self, out: bool) -> bool {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        wire.ready[self.side] = None;
+        match wire.ready[other].take() {
+            Some(bit) => {
+                wire.pending[other] = Some(out);
+                bit
+            }
+            None => true,
+        }
+    }
+
+    fn poll_external_clock(&mut self, out: Option<bool>) -> Option<bool> {
+        let mut wire = self.wire.borrow_mut();
+        if let Some(bit) = wire.pending[self.side].take() {
+            if out.is_some() {
+                return Some(bit);
+            }
+        }
+        wire.ready[self.side] = out;
+        None
+    }
+}
+
+// A pair of connected serial ports, for hosts that step the machines themselves
+pub fn cable() -> (LinkPort, LinkPort) {
+    let wire = Rc::new(RefCell::new(Wire::default()));
+    (
+        LinkPort { wire: wire.clone(), side: 0 },
+        LinkPort { wire, side: 1 },
+    )
+}
+
+pub struct LinkCable {
+    pub first: GameBoy,
+    pub second: GameBoy,
+}
+
+pub fn connect(mut first: GameBoy, mut second: GameBoy) -> LinkCable {
+    let (first_port, second_port) = cable();
+    first.attach_serial_device(Box::new(first_port));
+    second.attach_serial_device(Box::new(second_port));
+    LinkCable { first, second }
+}
+
+impl LinkCable {
+    // Advances both machines by one M-cycle
+    pub fn tick(&mut self) {
+        self.first.tick();
+        self.second.tick();
+    }
+
+    pub fn run_cycles(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.tick();
+        }
+    }
+
+    pub fn disconnect(mut self) -> (GameBoy, GameBoy) {
+        self.first.detach_serial_device();
+        self.second.detach_serial_device();
+        (self.first, self.second)
+    }
+}
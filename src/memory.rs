@@ -1,3 +1,7 @@
+use crate::cdl::{self, CodeDataLog};
+use crate::cheats::Cheats;
+use crate::debugger::{self, Watches};
+use crate::stats::AccessCounts;
 pub const GB_RAM_SIZE: usize = 0x10000;
 pub const GB_ROM_SIZE: usize = 0x100;
 pub fn init() -> FlatRAM {
@@ -6,15 +10,55 @@ pub fn init() -> FlatRAM {
 
 pub type FlatRAM = [u8; GB_RAM_SIZE];
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub enum MappingType {
     Flat, // all addresses readable and writable
     Default, // normal DMG behaviour with no MBCs (wip)
 }
+// What writes to 0x2000-0x3FFF do, from the cartridge type in the header at 0x0147
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Mbc {
+    None, // ROM only, the writes are ignored
+    Mbc1, // the low 5 bits select the bank, bank 0 maps to bank 1
+    Gbs, // GBS rips write the whole bank number
+}
+
+impl Mbc {
+    // Other controllers aren't emulated yet and get MBC1's bank select
+    pub fn from_header(rom: &[u8]) -> Mbc {
+        match rom.get(0x0147) {
+            None | Some(0x00) | Some(0x08) | Some(0x09) => Mbc::None,
+            _ => Mbc::Mbc1,
+        }
+    }
+
+    fn rom_bank(self, data: u8) -> Option<usize> {
+        match self {
+            Mbc::None => None,
+            Mbc::Mbc1 => Some(match data & 0x1F {
+                0 => 1,
+                bank => bank as usize,
+            }),
+            Mbc::Gbs => Some(data as usize),
+        }
+    }
+}
+
 pub struct MappedRAM {
     pub mapping_type: MappingType,
     pub main: [u8; GB_RAM_SIZE],
     pub rom: [u8; GB_ROM_SIZE],
+    pub cartridge: Vec<u8>, // full ROM image, empty if the cartridge area is backed by main
+    pub rom_bank: usize, // bank mapped at 0x4000-0x7FFF when a cartridge is loaded
+    pub mbc: Mbc,
+    pub joypad: u8, // pressed buttons, see joypad.rs
+    pub ly_stub: Option<u8>, // value the CPU reads from LY instead of the real one, see trace.rs
+    pub watches: Watches, // watchpoints, see debugger.rs
+    pub cdl: Option<CodeDataLog>, // see cdl.rs
+    pub access_counts: Option<AccessCounts>, // see stats.rs
+    pub cheats: Cheats, // see cheats.rs
+    pub(crate) interrupts_stale: bool, // see GameBoy::update_interrupt_requests()
+    pub(crate) serial_stale: bool, // see GameBoy::serial_needs_polling()
 }
 pub trait Memory {
     fn read(&self, address: u16) -> u8;
@@ -23,6 +67,36 @@ pub trait Memory {
 
 impl Memory for MappedRAM {
     fn read(&self, address: u16) -> u8 {
+        let data = self.read_mapped(address);
+        if self.watches.armed {
+            self.watches.check(address, debugger::READ, data);
+            if let Some(counts) = self.access_counts.as_ref() {
+                counts.read(address);
+            }
+            if let Some(cdl) = self.cdl.as_ref() {
+                if !cdl.instruction.as_ref().is_some_and(|instruction| instruction.contains(&address)) {
+                    if let Some(offset) = self.rom_offset(address) {
+                        cdl.mark(offset, cdl::DATA);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if self.watches.armed {
+            self.watches.check(address, debugger::WRITE, data);
+            if let Some(counts) = self.access_counts.as_ref() {
+                counts.write(address);
+            }
+        }
+        self.write_mapped(address, data);
+    }
+}
+
+impl MappedRAM {
+    fn read_mapped(&self, address: u16) -> u8 {
         if (address as usize) >= GB_RAM_SIZE {
             0
         } else {
@@ -31,24 +105,112 @@ impl Memory for MappedRAM {
                     return self.rom[address as usize];
                 }
                 if address == 0xFF00 {
-                    return 0xFF // TODO implement input
+                    return self.read_joypad();
+                }
+                if let (0xFF44, Some(ly)) = (address, self.ly_stub) {
+                    return ly;
+                }
+                if !self.cartridge.is_empty() && address <= 0x7FFF {
+                    let data = self.read_cartridge(address);
+                    if self.cheats.is_empty() {
+                        return data;
+                    }
+                    return self.cheats.patch_rom(address, data);
                 }
             }
             return self.main[address as usize];
         }
     }
 
-    fn write(&mut self, address: u16, data: u8) {
+    fn write_mapped(&mut self, address: u16, data: u8) {
         if (address as usize) >= GB_RAM_SIZE {
             ()
         } else {
             // check we are not trying to write to a cartridge or otherwise illegal area
             if self.mapping_type == MappingType::Default {
+                if !self.cartridge.is_empty() && (0x2000..=0x3FFF).contains(&address) {
+                    if let Some(bank) = self.mbc.rom_bank(data) {
+                        self.rom_bank = bank;
+                        return;
+                    }
+                }
                 if (self.main[0xFF50] != 0) && ((address <= 0x7FFF) || (address >= 0xE000 && address <= 0xFDFF) || (address >= 0xFEA0 && address <= 0xFEFF)) {
                     return;
                 } 
+                if address == 0xFF46 {
+                    self.oam_dma(data);
+                }
+            }
+            match address {
+                0xFF0F | 0xFF41 | 0xFF44 | 0xFF45 | 0xFFFF => self.interrupts_stale = true,
+                0xFF02 => self.serial_stale = true,
+                _ => (),
             }
             self.main[address as usize] = data
         }
     }
+
+    // OAM DMA from `page` * 0x100, copied all at once rather than over 160 M-cycles
+    fn oam_dma(&mut self, page: u8) {
+        let source = (page as u16) << 8;
+        for i in 0..0xA0 {
+            let data = self.read_mapped(source + i);
+            if let (Some(cdl), Some(offset)) = (self.cdl.as_ref(), self.rom_offset(source + i)) {
+                cdl.mark(offset, cdl::DMA_SOURCE);
+            }
+            self.main[0xFE00 + i as usize] = data;
+        }
+    }
+
+    // The ROM bank mapped at an address, or None where nothing is banked
+    pub fn bank(&self, address: u16) -> Option<u16> {
+        match address {
+            0x0000..=0x3FFF if self.cartridge.len() > 0x8000 => Some(0),
+            0x4000..=0x7FFF if self.cartridge.len() > 0x8000 => Some(self.rom_bank as u16),
+            _ => None,
+        }
+    }
+
+    pub fn load_cartridge(&mut self, rom: Vec<u8>) {
+        self.mbc = Mbc::from_header(&rom);
+        self.cartridge = rom;
+        self.rom_bank = 1;
+    }
+
+    // P1: bits 4 and 5 select the d-pad and buttons (0 = selected), the low nibble reads 0 for pressed
+    fn read_joypad(&self) -> u8 {
+        let select = self.main[0xFF00] & 0x30;
+        let mut lines = 0x0F;
+        if select & 0x10 == 0 {
+            lines &= !self.joypad & 0x0F;
+        }
+        if select & 0x20 == 0 {
+            lines &= !(self.joypad >> 4) & 0x0F;
+        }
+        0xC0 | select | lines
+    }
+
+    // Where an address the CPU reads is in the cartridge ROM image, None if it isn't ROM
+    pub fn rom_offset(&self, address: u16) -> Option<usize> {
+        let boot_rom = self.main[0xFF50] == 0 && (address as usize) < GB_ROM_SIZE;
+        if self.mapping_type != MappingType::Default || self.cartridge.is_empty() || boot_rom || address > 0x7FFF {
+            return None;
+        }
+        Some(self.cartridge_offset(address)).filter(|offset| *offset < self.cartridge.len())
+    }
+
+    fn cartridge_offset(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => address as usize,
+            _ => self.rom_bank * 0x4000 + (address as usize - 0x4000),
+        }
+    }
+
+    fn read_cartridge(&self, address: u16) -> u8 {
+        let offset = self.cartridge_offset(address);
+        match self.cartridge.get(offset) {
+            Some(data) => *data,
+            None => 0xFF, // open bus past the end of the image
+        }
+    }
 }
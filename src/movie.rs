@@ -0,0 +1,198 @@
+// Input movies: a starting point plus the buttons held for each frame, replayed bit for bit.
+//
+// File layout, integers little-endian:
+//   "DMGMOVIE", format version (u16), model (4 bytes, "DMG "), CRC-32 of the cartridge ROM (u32)
+//   start kind (u8): 0 = power on, 1 = save state
+//     power on: flags (u8), bit 0 set if the boot ROM was skipped
+//     save state: length (u32) then the state from GameBoy::save_state
+//   frame count (u32), then one byte of buttons (see joypad.rs) per frame
+//
+// A frame is CYCLES_PER_FRAME M-cycles from the starting point, so playback doesn't depend on
+// the PPU reaching VBlank at any particular time.
+use crate::gb::{GameBoy, CYCLES_PER_FRAME};
+use crate::util::{crc32, ReadError, Reader};
+
+pub const MOVIE_MAGIC: &[u8; 8] = b"DMGMOVIE";
+pub const MOVIE_VERSION: u16 = 1;
+pub const MODEL_DMG: &[u8; 4] = b"DMG ";
+
+#[derive(Debug, PartialEq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnsupportedModel([u8; 4]),
+    Truncated,
+    RomMismatch { expected: u32, found: u32 },
+    BadState,
+}
+
+impl From<ReadError> for MovieError {
+    fn from(_: ReadError) -> MovieError {
+        MovieError::Truncated
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MovieStart {
+    PowerOn { skip_boot_rom: bool },
+    State(Vec<u8>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Movie {
+    pub model: [u8; 4],
+    pub rom_crc32: u32,
+    pub start: MovieStart,
+    pub inputs: Vec<u8>,
+}
+
+pub struct Recorder {
+    movie: Movie,
+}
+
+pub struct Player {
+    movie: Movie,
+    frame: usize,
+}
+
+fn rom_crc32(gameboy: &GameBoy) -> u32 {
+    crc32(&gameboy.memory.cartridge)
+}
+
+fn run_frame(gameboy: &mut GameBoy, buttons: u8) {
+    gameboy.set_buttons(buttons);
+    let mut cycles = 0;
+    while cycles < CYCLES_PER_FRAME as u64 {
+        cycles += gameboy.advance(CYCLES_PER_FRAME as u64 - cycles);
+    }
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(MOVIE_MAGIC);
+        output.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        output.extend_from_slice(&self.model);
+        output.extend_from_slice(&self.rom_crc32.to_le_bytes());
+        match &self.start {
+            MovieStart::PowerOn { skip_boot_rom } => {
+                output.push(0);
+                output.push(*skip_boot_rom as u8);
+            }
+            MovieStart::State(state) => {
+                output.push(1);
+                output.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                output.extend_from_slice(state);
+            }
+        }
+        output.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+        output.extend_from_slice(&self.inputs);
+        output
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = Reader::new(data, 0);
+        if reader.take(8)? != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let model: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let rom_crc32 = reader.u32()?;
+        let start = match reader.u8()? {
+            0 => MovieStart::PowerOn {
+                skip_boot_rom: reader.u8()? & 1 != 0,
+            },
+            1 => {
+                let length = reader.u32()? as usize;
+                MovieStart::State(reader.take(length)?.to_vec())
+            }
+            _ => return Err(MovieError::BadState),
+        };
+        let frames = reader.u32()? as usize;
+        let inputs = reader.take(frames)?.to_vec();
+        Ok(Movie { model, rom_crc32, start, inputs })
+    }
+}
+
+impl Recorder {
+    // Starts recording from the Game Boy's current state
+    pub fn from_state(gameboy: &GameBoy) -> Recorder {
+        Recorder::new(gameboy, MovieStart::State(gameboy.save_state()))
+    }
+
+    // Starts recording from a freshly initialised Game Boy with its cartridge loaded
+    pub fn from_power_on(gameboy: &mut GameBoy, skip_boot_rom: bool) -> Recorder {
+        if skip_boot_rom {
+            gameboy.skip_boot_rom();
+        }
+        Recorder::new(gameboy, MovieStart::PowerOn { skip_boot_rom })
+    }
+
+    fn new(gameboy: &GameBoy, start: MovieStart) -> Recorder {
+        Recorder {
+            movie: Movie {
+                model: *MODEL_DMG,
+                rom_crc32: rom_crc32(gameboy),
+                start,
+                inputs: Vec::new(),
+            },
+        }
+    }
+
+    // Runs one frame with `buttons` held, logging them
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy, buttons: u8) {
+        self.movie.inputs.push(buttons);
+        run_frame(gameboy, buttons);
+    }
+
+    pub fn frames(&self) -> usize {
+        self.movie.inputs.len()
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+impl Player {
+    // Puts the Game Boy at the movie's starting point. For power on starts it has to be freshly
+    // initialised, with the movie's cartridge loaded.
+    pub fn new(movie: Movie, gameboy: &mut GameBoy) -> Result<Player, MovieError> {
+        if &movie.model != MODEL_DMG {
+            return Err(MovieError::UnsupportedModel(movie.model));
+        }
+        let found = rom_crc32(gameboy);
+        if found != movie.rom_crc32 {
+            return Err(MovieError::RomMismatch { expected: movie.rom_crc32, found });
+        }
+        match &movie.start {
+            MovieStart::PowerOn { skip_boot_rom: true } => gameboy.skip_boot_rom(),
+            MovieStart::PowerOn { skip_boot_rom: false } => (),
+            MovieStart::State(state) => gameboy.load_state(state).map_err(|_| MovieError::BadState)?,
+        }
+        Ok(Player { movie, frame: 0 })
+    }
+
+    // Runs the next frame of the movie, returning false once every frame has been played
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy) -> bool {
+        match self.movie.inputs.get(self.frame) {
+            Some(&buttons) => {
+                run_frame(gameboy, buttons);
+                self.frame += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.inputs.len()
+    }
+}
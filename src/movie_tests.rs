@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod movie_test {
+    use crate::gb;
+    use crate::joypad;
+    use crate::memory::Memory;
+    use crate::movie::{Movie, MovieError, Player, Recorder};
+    use crate::util::{fingerprint, test_gameboy};
+
+    fn joypad_gameboy() -> gb::GameBoy {
+        let program = [
+            0x21, 0x00, 0xC0, // LD HL, 0xC000
+            0x3E, 0x20, // LD A, 0x20
+            0xE0, 0x00, // LDH (0x00), A
+            0xF0, 0x00, // LDH A, (0x00)
+            0x47, // LD B, A
+            0x7E, // LD A, (HL)
+            0x80, // ADD A, B
+            0x77, // LD (HL), A
+            0x2C, // INC L
+            0x18, 0xF3, // JR -13
+        ];
+        test_gameboy(&program)
+    }
+
+    #[test]
+    fn joypad_reads_selected_buttons() {
+        let mut gameboy = joypad_gameboy();
+        gameboy.press(joypad::RIGHT | joypad::START);
+        gameboy.memory.write(0xFF00, 0x20);
+        assert_eq!(gameboy.memory.read(0xFF00) & 0x0F, 0x0E);
+        gameboy.memory.write(0xFF00, 0x10);
+        assert_eq!(gameboy.memory.read(0xFF00) & 0x0F, 0x07);
+        assert_ne!(gameboy.memory.read(0xFF0F) & 0b10000, 0, "joypad interrupt not requested");
+        gameboy.release(joypad::RIGHT);
+        assert_eq!(gameboy.buttons(), joypad::START);
+    }
+
+    #[test]
+    fn movie_playback_is_identical() {
+        let mut gameboy = joypad_gameboy();
+        let mut recorder = Recorder::from_power_on(&mut gameboy, true);
+        let mut frames = Vec::new();
+        for frame in 0..20u8 {
+            recorder.run_frame(&mut gameboy, frame % 16);
+            frames.push(fingerprint(&gameboy));
+        }
+        let movie = Movie::from_bytes(&recorder.finish().to_bytes()).expect("Could not parse movie");
+
+        let mut replay = joypad_gameboy();
+        let mut player = Player::new(movie.clone(), &mut replay).expect("Could not start movie");
+        for expected in frames.iter() {
+            assert!(player.run_frame(&mut replay));
+            assert!(fingerprint(&replay) == *expected, "playback diverged at frame {}", player.frame());
+        }
+        assert!(!player.run_frame(&mut replay));
+
+        let mut other_rom = gb::init();
+        other_rom.memory.load_cartridge(vec![0u8; 0x8000]);
+        assert!(matches!(Player::new(movie, &mut other_rom), Err(MovieError::RomMismatch { .. })));
+    }
+}
@@ -0,0 +1,208 @@
+// Soft-patching ROM images with IPS, UPS or BPS patches, told apart by their magic.
+//
+// IPS: "PATCH", then records of a big-endian u24 offset and u16 size followed by the data, or a
+//   size of 0 followed by a u16 count and a byte to repeat. "EOF" ends it, optionally followed by
+//   a u24 length to truncate the ROM to. There are no checksums.
+// UPS: "UPS1", source and target sizes, then runs of (bytes to skip, bytes XORed with the source
+//   up to a 0). Ends with the CRC-32s of the source, target and patch.
+// BPS: "BPS1", source and target sizes, metadata, then actions copying from the source, the
+//   patch or the target written so far. Ends with the same three CRC-32s as UPS.
+// UPS and BPS numbers are variable-length: 7 bits per byte, the last byte has bit 7 set.
+// https://www.romhacking.net/documents/392/ (UPS) and https://www.romhacking.net/documents/746/ (BPS)
+use crate::gb::GameBoy;
+use crate::util::{crc32, ReadError, Reader};
+
+// The largest cartridge an MBC can address; bigger UPS or BPS targets are rejected before allocating
+pub const MAX_TARGET_SIZE: usize = 8 << 20;
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    SourceSize { expected: usize, found: usize },
+    SourceChecksum { expected: u32, found: u32 },
+    TargetChecksum { expected: u32, found: u32 },
+    PatchChecksum { expected: u32, found: u32 },
+    OutOfBounds, // an action reads or writes outside the ROM
+    TargetTooLarge(usize),
+}
+
+impl From<ReadError> for PatchError {
+    fn from(error: ReadError) -> PatchError {
+        match error {
+            ReadError::Truncated => PatchError::Truncated,
+            ReadError::Overflow => PatchError::OutOfBounds,
+        }
+    }
+}
+
+// Applies a patch in any of the supported formats
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut output = rom.to_vec();
+    let mut reader = Reader::new(patch, 5);
+    loop {
+        let offset = reader.u24_be()? as usize;
+        if offset == 0x454F46 {
+            // "EOF"
+            break;
+        }
+        let (data, length) = match reader.u16_be()? as usize {
+            0 => {
+                let count = reader.u16_be()? as usize;
+                (vec![reader.u8()?; count], count)
+            }
+            size => (reader.take(size)?.to_vec(), size),
+        };
+        if output.len() < offset + length {
+            output.resize(offset + length, 0);
+        }
+        output[offset..offset + length].copy_from_slice(&data);
+    }
+    if reader.offset + 3 <= patch.len() {
+        let length = reader.u24_be()? as usize;
+        output.truncate(length);
+    }
+    Ok(output)
+}
+
+// Checks the footer shared by UPS and BPS, returning the expected target CRC-32
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < 16 {
+        return Err(PatchError::Truncated);
+    }
+    let mut footer = Reader::new(patch, patch.len() - 12);
+    let (source, target, expected) = (footer.u32()?, footer.u32()?, footer.u32()?);
+    let found = crc32(&patch[..patch.len() - 4]);
+    if found != expected {
+        return Err(PatchError::PatchChecksum { expected, found });
+    }
+    let found = crc32(rom);
+    if found != source {
+        return Err(PatchError::SourceChecksum { expected: source, found });
+    }
+    Ok(target)
+}
+
+fn check_target(output: Vec<u8>, expected: u32) -> Result<Vec<u8>, PatchError> {
+    let found = crc32(&output);
+    match found == expected {
+        true => Ok(output),
+        false => Err(PatchError::TargetChecksum { expected, found }),
+    }
+}
+
+fn check_target_size(size: usize) -> Result<usize, PatchError> {
+    match size <= MAX_TARGET_SIZE {
+        true => Ok(size),
+        false => Err(PatchError::TargetTooLarge(size)),
+    }
+}
+
+fn check_source_size(rom: &[u8], expected: usize) -> Result<(), PatchError> {
+    match rom.len() == expected {
+        true => Ok(()),
+        false => Err(PatchError::SourceSize { expected, found: rom.len() }),
+    }
+}
+
+pub fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(&patch[..end], 4);
+    check_source_size(rom, reader.number()?)?;
+    let mut output = rom.to_vec();
+    output.resize(check_target_size(reader.number()?)?, 0);
+    let mut offset = 0;
+    while reader.offset < end {
+        offset += reader.number()?;
+        loop {
+            let byte = reader.u8()?;
+            if byte == 0 {
+                offset += 1;
+                break;
+            }
+            if let Some(target) = output.get_mut(offset) {
+                *target = rom.get(offset).copied().unwrap_or(0) ^ byte;
+            }
+            offset += 1;
+        }
+    }
+    check_target(output, target_crc)
+}
+
+// A relative offset as BPS stores it: the sign in bit 0, the magnitude above it
+fn relative(offset: usize, encoded: usize) -> Result<usize, PatchError> {
+    let distance = encoded >> 1;
+    let moved = match encoded & 1 {
+        0 => offset.checked_add(distance),
+        _ => offset.checked_sub(distance),
+    };
+    moved.ok_or(PatchError::OutOfBounds)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc = check_footer(rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(&patch[..end], 4);
+    check_source_size(rom, reader.number()?)?;
+    let target_size = check_target_size(reader.number()?)?;
+    let metadata = reader.number()?;
+    reader.take(metadata)?;
+    let mut output = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while reader.offset < end {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        match action & 3 {
+            0 => {
+                // SourceRead, from the same offset in the source
+                let start = output.len();
+                output.extend_from_slice(rom.get(start..start + length).ok_or(PatchError::OutOfBounds)?);
+            }
+            1 => {
+                // TargetRead, from the patch
+                output.extend_from_slice(reader.take(length)?);
+            }
+            2 => {
+                // SourceCopy
+                source_offset = relative(source_offset, reader.number()?)?;
+                output.extend_from_slice(rom.get(source_offset..source_offset + length).ok_or(PatchError::OutOfBounds)?);
+                source_offset += length;
+            }
+            _ => {
+                // TargetCopy, byte by byte as the copy may overlap what it writes
+                target_offset = relative(target_offset, reader.number()?)?;
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+        if output.len() > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+    }
+    check_target(output, target_crc)
+}
+
+impl GameBoy {
+    // Loads a cartridge after applying a patch to it; nothing is loaded if the patch fails
+    pub fn load_patched_cartridge(&mut self, rom: &[u8], patch: &[u8]) -> Result<(), PatchError> {
+        let patched = apply(rom, patch)?;
+        self.memory.load_cartridge(patched);
+        Ok(())
+    }
+}
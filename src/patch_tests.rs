@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod patch_test {
+    use crate::gb;
+    use crate::memory::Memory;
+    use crate::patch::{self, PatchError};
+    use crate::util::crc32;
+
+    fn number(mut value: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | low);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    // Appends the source, target and patch CRC-32s
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips() {
+        let rom = vec![0u8; 16];
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]); // 2 bytes at 2
+        ips.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0xCC]); // 3 x CC at 8
+        ips.extend_from_slice(&[0x00, 0x00, 0x12, 0x00, 0x01, 0xDD]); // past the end
+        ips.extend_from_slice(b"EOF");
+        let patched = patch::apply(&rom, &ips).unwrap();
+        assert_eq!(patched.len(), 19);
+        assert_eq!(&patched[0..5], &[0, 0, 0xAA, 0xBB, 0]);
+        assert_eq!(&patched[7..12], &[0, 0xCC, 0xCC, 0xCC, 0]);
+        assert_eq!(&patched[16..19], &[0, 0, 0xDD]);
+
+        ips.extend_from_slice(&[0x00, 0x00, 0x09]);
+        assert_eq!(patch::apply(&rom, &ips).unwrap().len(), 9);
+        assert_eq!(patch::apply(&rom, &ips[..ips.len() - 6]), Err(PatchError::Truncated));
+    }
+
+    #[test]
+    fn ups() {
+        let source: Vec<u8> = (0..16).collect();
+        let mut target = source.clone();
+        target[3] = 0xFF;
+        target[4] = 0x00;
+        target.extend_from_slice(&[0x11, 0x22]);
+        let mut ups = b"UPS1".to_vec();
+        ups.extend(number(16));
+        ups.extend(number(18));
+        ups.extend(number(3)); // skip to 3
+        ups.extend_from_slice(&[0x03 ^ 0xFF, 0x04, 0x00]);
+        ups.extend(number(10)); // the 0 ending a run counts as a byte, so this skips to 16
+        ups.extend_from_slice(&[0x11, 0x22, 0x00]);
+        let ups = with_footer(ups, &source, &target);
+        assert_eq!(patch::apply(&source, &ups), Ok(target.clone()));
+
+        let mut other = source.clone();
+        other[0] = 1;
+        assert_eq!(
+            patch::apply(&other, &ups),
+            Err(PatchError::SourceChecksum { expected: crc32(&source), found: crc32(&other) })
+        );
+        let mut corrupted = ups.clone();
+        corrupted[7] ^= 1;
+        assert!(matches!(patch::apply(&source, &corrupted), Err(PatchError::PatchChecksum { .. })));
+    }
+
+    #[test]
+    fn bps() {
+        let source = b"ABCDEFGH".to_vec();
+        let target = b"ABCxyEFxyEFxABC".to_vec();
+        let mut bps = b"BPS1".to_vec();
+        bps.extend(number(source.len()));
+        bps.extend(number(target.len()));
+        bps.extend(number(3));
+        bps.extend_from_slice(b"abc"); // metadata
+        bps.extend(number((3 - 1) << 2)); // SourceRead "ABC"
+        bps.extend(number((2 - 1) << 2 | 1)); // TargetRead "xy"
+        bps.extend_from_slice(b"xy");
+        bps.extend(number((2 - 1) << 2 | 2)); // SourceCopy "EF" from +4
+        bps.extend(number(4 << 1));
+        bps.extend(number((5 - 1) << 2 | 3)); // TargetCopy "xyEFx" from +3, reading its own first byte
+        bps.extend(number(3 << 1));
+        bps.extend(number((3 - 1) << 2 | 2)); // SourceCopy "ABC" from -6
+        bps.extend(number(6 << 1 | 1));
+        let patched = with_footer(bps.clone(), &source, &target);
+        assert_eq!(patch::apply(&source, &patched), Ok(target.clone()));
+
+        let wrong = b"ABCxyEFxyEFxABD".to_vec();
+        let patched = with_footer(bps, &source, &wrong);
+        assert_eq!(
+            patch::apply(&source, &patched),
+            Err(PatchError::TargetChecksum { expected: crc32(&wrong), found: crc32(&target) })
+        );
+        assert_eq!(patch::apply(&source, b"NOTAPATCH"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn huge_target_size() {
+        let source = vec![0u8; 16];
+        for magic in [b"UPS1", b"BPS1"] {
+            let mut patch = magic.to_vec();
+            patch.extend(number(source.len()));
+            patch.extend(number(usize::MAX >> 7));
+            patch.extend(number(0));
+            let patch = with_footer(patch, &source, &[]);
+            assert_eq!(patch::apply(&source, &patch), Err(PatchError::TargetTooLarge(usize::MAX >> 7)));
+        }
+    }
+
+    #[test]
+    fn loading_patched_cartridge() {
+        let rom = vec![0u8; 0x8000];
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0x00, 0x01, 0x50, 0x00, 0x01, 0x42]);
+        ips.extend_from_slice(b"EOF");
+        let mut gameboy = gb::init();
+        gameboy.load_patched_cartridge(&rom, &ips).unwrap();
+        gameboy.skip_boot_rom();
+        assert_eq!(gameboy.memory.read(0x0150), 0x42);
+    }
+}
@@ -0,0 +1,324 @@
+// Game Boy Printer, attached to the serial port. The Game Boy is always the clock master.
+//
+// Packets are: 0x88 0x33, command, compression, length (LE u16), data, checksum (LE u16),
+// then two 0x00 bytes during which the printer answers 0x81 (its ID) and its status.
+// The checksum is the 16 bit sum of every byte from the command to the end of the data.
+// https://gbdev.io/pandocs/Gameboy_Printer.html
+use crate::serial::SerialDevice;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::rc::Rc;
+
+pub const PRINTER_WIDTH: usize = 160;
+pub const PRINTER_BUFFER_SIZE: usize = 0x2000;
+const BAND_SIZE: usize = 0x280; // 20x2 tiles, one 160x16 strip
+const MARGIN_UNIT_LINES: usize = 16; // blank lines fed per unit of margin
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_IMAGE_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+const STATUS_PACKET_ERROR: u8 = 0x10;
+
+const PRINT_BUSY_POLLS: u8 = 4; // status replies that report printing after a PRINT
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PrintedPage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>, // one shade per pixel, 0 (lightest) to 3 (darkest), row-major like GameBoy::display
+}
+
+impl PrintedPage {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        let grey: Vec<u8> = self.pixels.iter().map(|&shade| [0xFF, 0xAA, 0x55, 0x00][(shade & 3) as usize]).collect();
+        writer.write_image_data(&grey).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Paper {
+    pages: Vec<PrintedPage>,
+    strip: Vec<u8>, // printed rows since the paper was last torn off
+}
+
+impl Paper {
+    fn feed(&mut self, lines: usize) {
+        self.strip.resize(self.strip.len() + lines * PRINTER_WIDTH, 0);
+    }
+
+    fn tear_off(&mut self) {
+        if !self.strip.is_empty() {
+            let pixels = std::mem::take(&mut self.strip);
+            self.pages.push(PrintedPage {
+                width: PRINTER_WIDTH,
+                height: pixels.len() / PRINTER_WIDTH,
+                pixels,
+            });
+        }
+    }
+}
+
+// Host side handle to what the printer has printed
+#[derive(Clone)]
+pub struct PrinterOutput {
+    paper: Rc<RefCell<Paper>>,
+}
+
+impl PrinterOutput {
+    // Pages finish when a print ends with a margin after it, as the paper is then fed out
+    pub fn pages(&self) -> Vec<PrintedPage> {
+        self.paper.borrow().pages.clone()
+    }
+
+    pub fn take_pages(&self) -> Vec<PrintedPage> {
+        std::mem::take(&mut self.paper.borrow_mut().pages)
+    }
+
+    // Finishes the page currently being printed, if anything has been printed on it
+    pub fn tear_off(&self) {
+        self.paper.borrow_mut().tear_off();
+    }
+}
+
+pub struct Printer {
+    paper: Rc<RefCell<Paper>>,
+    buffer: Vec<u8>, // received, decompressed image data
+    status: u8,
+    busy_polls: u8,
+
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+
+    shift_in: u8,
+    bits: u8,
+    reply: u8,
+}
+
+pub fn init() -> (Printer, PrinterOutput) {
+    let paper = Rc::new(RefCell::new(Paper::default()));
+    let printer = Printer {
+        paper: paper.clone(),
+        buffer: Vec::new(),
+        status: 0,
+        busy_polls: 0,
+        state: PacketState::Magic1,
+        command: 0,
+        compressed: false,
+        length: 0,
+        packet: Vec::new(),
+        checksum: 0,
+        received_checksum: 0,
+        shift_in: 0,
+        bits: 0,
+        reply: 0,
+    };
+    (printer, PrinterOutput { paper })
+}
+
+// Run-length decoding used by DATA packets: a control byte with bit 7 set repeats the next byte
+// (control & 0x7F) + 2 times, otherwise (control + 1) literal bytes follow.
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            if let Some(&byte) = data.get(i) {
+                output.resize(output.len() + (control & 0x7F) as usize + 2, byte);
+            }
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+    output
+}
+
+impl Printer {
+    // Handles a whole byte from the Game Boy, returning the byte to send during the next one
+    pub fn transfer_byte(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            PacketState::Magic1 => match byte {
+                0x88 => PacketState::Magic2,
+                _ => PacketState::Magic1,
+            },
+            PacketState::Magic2 => match byte {
+                0x33 => PacketState::Command,
+                0x88 => PacketState::Magic2,
+                _ => PacketState::Magic1,
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.packet.clear();
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                match self.length {
+                    0 => PacketState::ChecksumLow,
+                    _ => PacketState::Data,
+                }
+            }
+            PacketState::Data => {
+                self.packet.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                match self.packet.len() == self.length as usize {
+                    true => PacketState::ChecksumLow,
+                    false => PacketState::Data,
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                reply = 0x81;
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                self.execute();
+                reply = self.status;
+                if self.busy_polls > 0 {
+                    self.busy_polls -= 1;
+                    if self.busy_polls == 0 {
+                        self.status &= !STATUS_PRINTING;
+                    }
+                }
+                PacketState::Status
+            }
+            PacketState::Status => PacketState::Magic1,
+        };
+        reply
+    }
+
+    fn execute(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !(STATUS_CHECKSUM_ERROR | STATUS_PACKET_ERROR);
+        match self.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.busy_polls = 0;
+            }
+            COMMAND_DATA => {
+                let data = match self.compressed {
+                    true => decompress(&self.packet),
+                    false => std::mem::take(&mut self.packet),
+                };
+                let space = PRINTER_BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend_from_slice(&data[..data.len().min(space)]);
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.buffer.len() >= PRINTER_BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+            }
+            COMMAND_PRINT if self.packet.len() == 4 => {
+                let margins = self.packet[1];
+                let palette = match self.packet[2] {
+                    0 => 0xE4, // 0 is treated as the identity palette
+                    palette => palette,
+                };
+                if self.packet[0] > 0 {
+                    self.print(margins, palette);
+                }
+                self.buffer.clear();
+                self.status &= !(STATUS_UNPROCESSED | STATUS_IMAGE_FULL);
+                self.status |= STATUS_PRINTING;
+                self.busy_polls = PRINT_BUSY_POLLS;
+            }
+            COMMAND_STATUS => (),
+            _ => self.status |= STATUS_PACKET_ERROR,
+        }
+    }
+
+    fn print(&mut self, margins: u8, palette: u8) {
+        let mut paper = self.paper.borrow_mut();
+        paper.feed((margins >> 4) as usize * MARGIN_UNIT_LINES);
+        let height = self.buffer.len() / BAND_SIZE * 16;
+        for y in 0..height {
+            for x in 0..PRINTER_WIDTH {
+                let tile = (y / 8) * 20 + x / 8;
+                let address = tile * 16 + (y % 8) * 2;
+                let low = (self.buffer[address] >> (7 - x % 8)) & 1;
+                let high = (self.buffer[address + 1] >> (7 - x % 8)) & 1;
+                let colour = low | (high << 1);
+                paper.strip.push((palette >> (colour << 1)) & 0b11);
+            }
+        }
+        let after = (margins & 0x0F) as usize;
+        if after > 0 {
+            paper.feed(after * MARGIN_UNIT_LINES);
+            paper.tear_off();
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange_bit(&mut self, out: bool) -> bool {
+        let bit = self.reply & 0x80 != 0;
+        self.reply <<= 1;
+        self.shift_in = (self.shift_in << 1) | out as u8;
+        self.bits += 1;
+        if self.bits == 8 {
+            self.bits = 0;
+            self.reply = self.transfer_byte(self.shift_in);
+        }
+        bit
+    }
+}
@@ -0,0 +1,219 @@
+// Cycle profiler: attributes M-cycles to the instruction at each PC and to the routines on the
+// shadow call stack (callstack.rs), named from the symbol table where there is one.
+//
+// The cycles between two instruction fetches go to the first of them, so interrupt dispatch is
+// charged to the instruction it interrupted and idle cycles skipped by advance() still count.
+// Code that runs without any frame on the call stack is attributed to TOP.
+use crate::gb::GameBoy;
+use crate::symbols::Symbols;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+pub const TOP: &str = "(top)";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Routine {
+    pub address: u16,
+    pub bank: Option<u16>,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    pcs: HashMap<(u16, Option<u16>), u64>,
+    stacks: HashMap<Vec<Routine>, u64>, // outermost first
+    current: Option<(u16, Option<u16>, Vec<Routine>)>, // the instruction executing
+    since: u128, // clock when it was fetched
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PcCycles {
+    pub address: u16,
+    pub bank: Option<u16>,
+    pub name: Option<String>, // "label+$N"
+    pub cycles: u64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoutineCycles {
+    pub name: String,
+    pub self_cycles: u64, // in the routine itself
+    pub total_cycles: u64, // including the routines it called
+}
+
+// A profile with names resolved, see GameBoy::profile
+#[derive(Clone, PartialEq, Debug)]
+pub struct Profile {
+    pub total: u64,
+    pub pcs: Vec<PcCycles>, // most cycles first
+    pub stacks: Vec<(Vec<String>, u64)>, // routine names outermost first, in name order
+}
+
+fn routine_name(routine: &Routine, symbols: &Symbols) -> String {
+    match (symbols.name_at(routine.address, routine.bank), routine.bank) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(bank)) => format!("{:02X}:{:04X}", bank, routine.address),
+        (None, None) => format!("${:04X}", routine.address),
+    }
+}
+
+fn percent(cycles: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => cycles as f64 * 100.0 / total as f64,
+    }
+}
+
+impl Profiler {
+    fn snapshot(&self, clock: u128, symbols: &Symbols) -> Profile {
+        let mut pcs = self.pcs.clone();
+        let mut stacks = self.stacks.clone();
+        if let Some((pc, bank, stack)) = self.current.as_ref() {
+            let cycles = (clock - self.since) as u64;
+            *pcs.entry((*pc, *bank)).or_default() += cycles;
+            *stacks.entry(stack.clone()).or_default() += cycles;
+        }
+        let mut pcs: Vec<PcCycles> = pcs
+            .into_iter()
+            .map(|((address, bank), cycles)| PcCycles {
+                address,
+                bank,
+                name: symbols.describe(address, bank),
+                cycles,
+            })
+            .collect();
+        pcs.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.address.cmp(&b.address)));
+        let mut named: BTreeMap<Vec<String>, u64> = BTreeMap::new();
+        for (stack, cycles) in stacks {
+            let mut names = vec![TOP.to_string()];
+            names.extend(stack.iter().map(|routine| routine_name(routine, symbols)));
+            *named.entry(names).or_default() += cycles;
+        }
+        Profile {
+            total: pcs.iter().map(|pc| pc.cycles).sum(),
+            pcs,
+            stacks: named.into_iter().collect(),
+        }
+    }
+}
+
+impl Profile {
+    // Cycles per routine, most self cycles first
+    pub fn routines(&self) -> Vec<RoutineCycles> {
+        let mut routines: HashMap<&str, (u64, u64)> = HashMap::new();
+        for (stack, cycles) in &self.stacks {
+            for (depth, name) in stack.iter().enumerate() {
+                // recursion only counts once towards the total
+                if stack[..depth].contains(name) {
+                    continue;
+                }
+                routines.entry(name).or_default().1 += cycles;
+            }
+            routines.entry(stack.last().unwrap()).or_default().0 += cycles;
+        }
+        let mut routines: Vec<RoutineCycles> = routines
+            .into_iter()
+            .map(|(name, (self_cycles, total_cycles))| RoutineCycles {
+                name: name.to_string(),
+                self_cycles,
+                total_cycles,
+            })
+            .collect();
+        routines.sort_by(|a, b| b.self_cycles.cmp(&a.self_cycles).then(a.name.cmp(&b.name)));
+        routines
+    }
+
+    pub fn flat_report(&self) -> String {
+        let mut report = format!("{:>12} {:>6} {:>12} {:>6}  routine\n", "self", "%", "total", "%");
+        for routine in self.routines() {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>5.1}% {:>12} {:>5.1}%  {}",
+                routine.self_cycles,
+                percent(routine.self_cycles, self.total),
+                routine.total_cycles,
+                percent(routine.total_cycles, self.total),
+                routine.name
+            );
+        }
+        report
+    }
+
+    // The call tree, each routine with its total and self cycles, callees indented below it
+    pub fn tree_report(&self) -> String {
+        let mut nodes: BTreeMap<&[String], (u64, u64)> = BTreeMap::new();
+        for (stack, cycles) in &self.stacks {
+            for depth in 1..=stack.len() {
+                nodes.entry(&stack[..depth]).or_default().0 += cycles;
+            }
+            nodes.entry(&stack[..]).or_default().1 += cycles;
+        }
+        let mut report = String::new();
+        for (path, (total, self_cycles)) in nodes {
+            let _ = writeln!(
+                report,
+                "{:indent$}{} {} ({:.1}%), self {}",
+                "",
+                path.last().unwrap(),
+                total,
+                percent(total, self.total),
+                self_cycles,
+                indent = 2 * (path.len() - 1)
+            );
+        }
+        report
+    }
+
+    // Collapsed stacks, one "outer;inner cycles" line per stack, for flamegraph.pl and inferno
+    pub fn collapsed(&self) -> String {
+        let mut text = String::new();
+        for (stack, cycles) in &self.stacks {
+            let _ = writeln!(text, "{} {}", stack.join(";"), cycles);
+        }
+        text
+    }
+}
+
+impl GameBoy {
+    pub fn start_profiling(&mut self) {
+        self.profiler = Some(Profiler {
+            since: self.clock,
+            ..Default::default()
+        });
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        let profile = self.profile();
+        self.profiler = None;
+        profile
+    }
+
+    // The profile so far
+    pub fn profile(&self) -> Option<Profile> {
+        Some(self.profiler.as_ref()?.snapshot(self.clock, &self.symbols))
+    }
+
+    // Called when the instruction at PC is fetched
+    pub(crate) fn profile_instruction(&mut self) {
+        let pc = self.registers.pc;
+        let stack: Vec<Routine> = self
+            .call_stack()
+            .iter()
+            .rev()
+            .map(|frame| Routine {
+                address: frame.target,
+                bank: frame.bank,
+            })
+            .collect();
+        let bank = self.memory.bank(pc);
+        let clock = self.clock;
+        if let Some(profiler) = self.profiler.as_mut() {
+            if let Some((pc, bank, stack)) = profiler.current.take() {
+                let cycles = (clock - profiler.since) as u64;
+                *profiler.pcs.entry((pc, bank)).or_default() += cycles;
+                *profiler.stacks.entry(stack).or_default() += cycles;
+            }
+            profiler.current = Some((pc, bank, stack));
+            profiler.since = clock;
+        }
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod profiler_test {
+    use crate::profiler::{RoutineCycles, TOP};
+    use crate::run::StopReason;
+    use crate::util::test_gameboy_with;
+
+    #[test]
+    fn attributes_cycles_to_routines() {
+        let program = [
+            0xCD, 0x10, 0x01, // 0100: CALL 0x0110
+            0x18, 0xFB, // 0103: JR 0x0100
+        ];
+        let mut gameboy = test_gameboy_with(&[(0x100, &program), (0x110, &[0x00, 0xC9])]); // 0110: NOP, RET
+        gameboy.load_symbols("00:0110 Sub\n");
+        gameboy.start_profiling();
+        // 100 loops of 18 M-cycles, as this emulator times them
+        assert_eq!(gameboy.run_cycles(1800), StopReason::CyclesElapsed);
+
+        let profile = gameboy.stop_profiling().unwrap();
+        assert_eq!(profile.total, 1800);
+        assert_eq!(profile.pcs[0].address, 0x0100);
+        assert_eq!(profile.pcs[0].cycles, 700);
+        assert_eq!(profile.pcs[1].name.as_deref(), Some("Sub+$1"));
+        assert_eq!(profile.routines(), [
+            RoutineCycles { name: TOP.to_string(), self_cycles: 1100, total_cycles: 1800 },
+            RoutineCycles { name: "Sub".to_string(), self_cycles: 700, total_cycles: 700 },
+        ]);
+        assert_eq!(profile.collapsed(), "(top) 1100\n(top);Sub 700\n");
+        assert_eq!(profile.tree_report(), "(top) 1800 (100.0%), self 1100\n  Sub 700 (38.9%), self 700\n");
+        assert!(gameboy.profiler.is_none());
+    }
+}
@@ -0,0 +1,159 @@
+// Rewind buffer. Snapshots are taken every `interval` frames; only the newest is kept whole,
+// older ones are stored as the XOR against their successor, run-length encoded.
+// Between frames most of memory is unchanged, so a delta is a few hundred bytes rather than
+// a full save state (MappedRAM::main alone is 64 KiB).
+use crate::gb::GameBoy;
+use std::collections::VecDeque;
+
+enum Delta {
+    Xor(Vec<u8>), // RLE encoded XOR, same length as its successor
+    Full(Vec<u8>), // the state changed size, e.g. a GBS was loaded
+}
+
+pub struct Rewind {
+    pub interval: u32, // frames between snapshots
+    pub capacity: usize, // deltas kept, the oldest are dropped first
+    frames_since_capture: u32,
+    head: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>, // the newest delta turns the head into the snapshot before it
+}
+
+pub fn init(interval: u32, capacity: usize) -> Rewind {
+    Rewind {
+        interval: interval.max(1),
+        capacity,
+        frames_since_capture: 0,
+        head: None,
+        deltas: VecDeque::new(),
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = input.get(*offset) {
+        *offset += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+// XOR of the two states as (zero run, literal length, literal bytes) triples
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < from.len() {
+        let zeros_start = i;
+        while i < from.len() && from[i] == to[i] {
+            i += 1;
+        }
+        let literal_start = i;
+        while i < from.len() && from[i] != to[i] {
+            i += 1;
+        }
+        write_varint(&mut output, literal_start - zeros_start);
+        write_varint(&mut output, i - literal_start);
+        output.extend((literal_start..i).map(|j| from[j] ^ to[j]));
+    }
+    output
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut offset = 0;
+    let mut i = 0;
+    while offset < delta.len() {
+        i += read_varint(delta, &mut offset);
+        let literals = read_varint(delta, &mut offset);
+        for byte in &delta[offset..offset + literals] {
+            state[i] ^= byte;
+            i += 1;
+        }
+        offset += literals;
+    }
+}
+
+impl Rewind {
+    // Call once per emulated frame
+    pub fn record_frame(&mut self, gameboy: &GameBoy) {
+        self.frames_since_capture += 1;
+        if self.head.is_some() && self.frames_since_capture < self.interval {
+            return;
+        }
+        self.frames_since_capture = 0;
+        let state = gameboy.save_state();
+        if let Some(previous) = self.head.take() {
+            let delta = match previous.len() == state.len() {
+                true => Delta::Xor(encode_delta(&previous, &state)),
+                false => Delta::Full(previous),
+            };
+            self.deltas.push_back(delta);
+            while self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.head = Some(state);
+    }
+
+    // Restores the most recent snapshot older than the current frame.
+    // Returns false, leaving the Game Boy alone, once the buffer is exhausted.
+    pub fn step_back(&mut self, gameboy: &mut GameBoy) -> bool {
+        if self.frames_since_capture == 0 {
+            let head = match self.head.as_mut() {
+                Some(head) => head,
+                None => return false,
+            };
+            match self.deltas.pop_back() {
+                Some(Delta::Xor(delta)) => apply_delta(head, &delta),
+                Some(Delta::Full(state)) => *head = state,
+                None => return false,
+            }
+        }
+        self.frames_since_capture = 0;
+        match self.head.as_ref() {
+            Some(head) => gameboy.load_state(head).is_ok(),
+            None => false,
+        }
+    }
+
+    // Number of snapshots held, including the newest
+    pub fn len(&self) -> usize {
+        match self.head {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.deltas.clear();
+        self.frames_since_capture = 0;
+    }
+
+    // Bytes held by the buffer
+    pub fn memory_usage(&self) -> usize {
+        let deltas: usize = self
+            .deltas
+            .iter()
+            .map(|delta| match delta {
+                Delta::Xor(data) | Delta::Full(data) => data.len(),
+            })
+            .sum();
+        deltas + self.head.as_ref().map_or(0, |head| head.len())
+    }
+}
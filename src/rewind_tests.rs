@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod rewind_test {
+    use crate::rewind;
+    use crate::util::{fingerprint, test_gameboy, COUNTING_LOOP};
+
+    #[test]
+    fn rewind_steps_back_through_snapshots() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        let mut rewind = rewind::init(2, 8);
+        let mut captured = Vec::new();
+        for frame in 0..20 {
+            for _ in 0..17556 {
+                gameboy.tick();
+            }
+            rewind.record_frame(&gameboy);
+            if frame % 2 == 0 {
+                captured.push(fingerprint(&gameboy));
+            }
+        }
+        assert_eq!(rewind.len(), 9);
+        assert!(rewind.memory_usage() < 2 * gameboy.save_state().len(), "deltas should be compressed");
+
+        // the last frame wasn't captured, so the first step back lands on the one before it
+        for expected in captured.iter().rev().take(9) {
+            assert!(rewind.step_back(&mut gameboy));
+            assert!(fingerprint(&gameboy) == *expected, "rewound to the wrong state");
+        }
+        assert!(!rewind.step_back(&mut gameboy));
+    }
+}
@@ -0,0 +1,116 @@
+// Run loops for hosts, so frontends and tests don't each drive tick() themselves.
+// Every method returns why it stopped; breakpoints are checked at instruction boundaries,
+// except at the PC execution resumes from.
+use crate::debugger::WatchHit;
+use crate::gb::{GameBoy, CYCLES_PER_FRAME};
+use crate::memory::Memory;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StopReason {
+    FrameDone,
+    CyclesElapsed,
+    InstructionDone,
+    PredicateMet,
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    HaltedForever, // HALT or STOP, which nothing wakes the CPU from yet
+    IllegalOpcode(u8),
+}
+
+impl GameBoy {
+    // Why the CPU can't make progress, if it can't
+    fn stuck(&self) -> Option<StopReason> {
+        if !self.running {
+            return Some(StopReason::HaltedForever);
+        }
+        match self.cycles_to_idle {
+            Some(_) => None,
+            None => Some(StopReason::IllegalOpcode(self.memory.read(self.registers.pc.wrapping_sub(1)))),
+        }
+    }
+
+    // Advances by up to `limit` M-cycles, or reports why the run loop should stop instead
+    fn step(&mut self, resumed: &mut bool, limit: u64) -> Result<u64, StopReason> {
+        if let Some(reason) = self.stuck() {
+            return Err(reason);
+        }
+        if self.at_instruction_boundary() && !*resumed {
+            if let Some(reason) = self.check_breakpoints() {
+                return Err(reason);
+            }
+        }
+        *resumed = false;
+        let cycles = self.advance(limit);
+        match self.memory.watches.take_hit() {
+            Some(hit) => Err(StopReason::Watchpoint(hit)),
+            None => Ok(cycles),
+        }
+    }
+
+    // Runs until the PPU publishes the next frame to display. Gives up after two frames' worth
+    // of cycles, as nothing is published while the PPU is off (in test mode).
+    pub fn run_frame(&mut self) -> StopReason {
+        let frame = self.frame_count;
+        let limit = 2 * CYCLES_PER_FRAME as u64;
+        let mut cycles = 0;
+        let mut resumed = true;
+        while cycles < limit {
+            match self.step(&mut resumed, limit - cycles) {
+                Ok(advanced) => cycles += advanced,
+                Err(reason) => return reason,
+            }
+            if self.frame_count != frame {
+                return StopReason::FrameDone;
+            }
+        }
+        StopReason::CyclesElapsed
+    }
+
+    pub fn run_cycles(&mut self, cycles: u64) -> StopReason {
+        let mut elapsed = 0;
+        let mut resumed = true;
+        while elapsed < cycles {
+            match self.step(&mut resumed, cycles - elapsed) {
+                Ok(advanced) => elapsed += advanced,
+                Err(reason) => return reason,
+            }
+        }
+        StopReason::CyclesElapsed
+    }
+
+    // Runs until `predicate` holds, checking it after every M-cycle
+    pub fn run_until(&mut self, mut predicate: impl FnMut(&GameBoy) -> bool) -> StopReason {
+        let mut resumed = true;
+        loop {
+            if let Err(reason) = self.step(&mut resumed, 1) {
+                return reason;
+            }
+            if predicate(self) {
+                return StopReason::PredicateMet;
+            }
+        }
+    }
+
+    // Runs until the next instruction has been fetched and all of its cycles have passed,
+    // including any interrupt dispatch that comes first
+    pub fn run_instruction(&mut self) -> StopReason {
+        self.run_to_boundary(|_| true)
+    }
+
+    // Runs instructions until `done` holds at an instruction boundary
+    pub(crate) fn run_to_boundary(&mut self, mut done: impl FnMut(&GameBoy) -> bool) -> StopReason {
+        let mut instructions = self.instructions;
+        let mut resumed = true;
+        loop {
+            if let Err(reason) = self.step(&mut resumed, u64::MAX) {
+                return reason;
+            }
+            if self.instructions != instructions && self.at_instruction_boundary() {
+                if done(self) {
+                    return StopReason::InstructionDone;
+                }
+                instructions = self.instructions;
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod run_test {
+    use crate::gb::{self, CYCLES_PER_FRAME};
+    use crate::run::StopReason;
+    use crate::memory::Memory;
+    use crate::util::{test_gameboy, test_gameboy_with, COUNTING_LOOP};
+
+    #[test]
+    fn run_frame_stops_when_frame_is_published() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        assert_eq!(gameboy.run_frame(), StopReason::FrameDone);
+        assert_eq!(gameboy.frame_count, 1);
+        let clock = gameboy.clock;
+        assert_eq!(gameboy.run_frame(), StopReason::FrameDone);
+        assert_eq!(gameboy.frame_count, 2);
+        assert_eq!(gameboy.clock - clock, CYCLES_PER_FRAME as u128);
+    }
+
+    #[test]
+    fn breakpoints_stop_before_the_instruction() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        gameboy.add_breakpoint(0x0104, None);
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x0104));
+        assert_eq!(gameboy.registers.a, 2); // A is 1 after the boot ROM
+        assert_eq!(gameboy.memory.main[0xC000], 0);
+
+        // resuming runs the instruction under the breakpoint before stopping at it again
+        assert_eq!(gameboy.run_cycles(1000), StopReason::Breakpoint(0x0104));
+        assert_eq!(gameboy.registers.a, 3);
+        assert_eq!(gameboy.memory.main[0xC000], 2);
+    }
+
+    #[test]
+    fn run_instruction_steps_one_instruction() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        assert_eq!(gameboy.run_instruction(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x0103);
+        assert_eq!(gameboy.get_hl(), 0xC000);
+        assert_eq!(gameboy.run_instruction(), StopReason::InstructionDone);
+        assert_eq!(gameboy.registers.pc, 0x0104);
+        assert_eq!(gameboy.registers.a, 2);
+    }
+
+    #[test]
+    fn run_until_predicate() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        assert_eq!(gameboy.run_until(|gameboy| gameboy.memory.main[0xC010] != 0), StopReason::PredicateMet);
+        assert_eq!(gameboy.memory.main[0xC010], 0x12);
+    }
+
+    #[test]
+    fn reports_stuck_cpu() {
+        let mut halted = test_gameboy(&[0x00, 0x76]); // NOP, HALT
+        assert_eq!(halted.run_frame(), StopReason::HaltedForever);
+        assert_eq!(halted.run_until(|_| false), StopReason::HaltedForever);
+
+        let mut illegal = test_gameboy(&[0x00, 0xD3]);
+        assert_eq!(illegal.run_cycles(100), StopReason::IllegalOpcode(0xD3));
+        assert_eq!(illegal.run_instruction(), StopReason::IllegalOpcode(0xD3));
+    }
+
+    #[test]
+    fn skipping_idle_cycles_matches_ticking() {
+        let program = [
+            0x3E, 0x41, // LD A, 'A'
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 0x81
+            0xE0, 0x02, // LDH (SC), A
+            0xCD, 0x10, 0x01, // CALL 0x0110
+            0x18, 0xF3, // JR -13
+            0x00, 0x00, 0x00,
+            0xF5, // PUSH AF
+            0xF1, // POP AF
+            0xC9, // RET
+        ];
+        let fingerprint = |gameboy: &gb::GameBoy| {
+            (
+                gameboy.clock,
+                gameboy.registers.pc,
+                gameboy.memory.main.to_vec(),
+                gameboy.display.to_vec(),
+                gameboy.serial_output().to_string(),
+            )
+        };
+        let start = || {
+            let mut gameboy = test_gameboy(&program);
+            gameboy.memory.main[0xFFFF] = 0x09; // VBlank and serial, whose vectors slide through NOPs back into the program
+            gameboy.ime = true;
+            gameboy.capture_serial();
+            gameboy
+        };
+
+        let mut ticked = start();
+        for _ in 0..100000 {
+            ticked.tick();
+        }
+        let mut skipped = start();
+        assert_eq!(skipped.run_cycles(100000), StopReason::CyclesElapsed);
+        assert!(fingerprint(&skipped) == fingerprint(&ticked), "skipping idle cycles changed execution");
+        assert!(!ticked.serial_output().is_empty());
+    }
+
+    #[test]
+    fn host_writes_update_interrupt_requests() {
+        let mut gameboy = test_gameboy_with(&[(0x100, &[0xCD, 0x00, 0x02]), (0x200, &[0x18, 0xFE])]); // CALL 0x0200
+        gameboy.tick(); // CALL runs, the next few ticks are idle
+        gameboy.tick(); // and the requests are brought up to date after it
+        let ly = gameboy.memory.read(0xFF44);
+        gameboy.memory.write(0xFF0F, 0x00);
+        gameboy.memory.write(0xFF45, ly);
+        gameboy.memory.write(0xFF41, 0x40); // LY=LYC interrupt
+        gameboy.tick();
+        assert_ne!(gameboy.cycles_to_idle, Some(0), "still inside CALL");
+        assert_eq!(gameboy.memory.read(0xFF0F) & 0b10, 0b10);
+    }
+}
@@ -0,0 +1,170 @@
+// Event scheduler. Subsystems schedule work for the M-cycle it's due on instead of checking
+// the clock every tick, and run loops use the gaps between events to skip the idle M-cycles of
+// long instructions in one go.
+//
+// The PPU and the internally clocked serial shift are events. Interrupt requests are only
+// recomputed after an instruction, an ISR step, an event or a write to their registers, and the
+// serial port is only polled while a transfer is starting or externally clocked. The CPU still executes instruction by
+// instruction. OAM DMA copies at once, and the timer and the APU aren't emulated yet, so they
+// have no events.
+//
+// Events are processed at the end of the tick whose clock (before incrementing) matches their
+// timestamp; events due on the same cycle run in EventKind order. Nothing here is saved: the
+// queue is rebuilt from the clock and the subsystems' own state after loading a save state.
+use crate::gb::{GameBoy, IsrState};
+use crate::memory::Memory;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub const CYCLES_PER_LINE: u128 = 114;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum EventKind {
+    SerialBit, // internally clocked serial shift
+    Ppu(PpuEvent),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum PpuEvent {
+    LineStart, // start of a scanline, also VBlank entry on line 144
+    Draw,      // the line is rendered
+    HBlank,    // LY is incremented
+    LineEnd,   // after line 153 the frame is published and LY wraps
+}
+
+impl PpuEvent {
+    // M-cycle within each scanline the event falls on
+    fn line_offset(self) -> u128 {
+        match self {
+            PpuEvent::LineStart => 0,
+            PpuEvent::Draw => 20,
+            PpuEvent::HBlank => 63,
+            PpuEvent::LineEnd => 113,
+        }
+    }
+}
+
+const PPU_EVENTS: [PpuEvent; 4] = [PpuEvent::LineStart, PpuEvent::Draw, PpuEvent::HBlank, PpuEvent::LineEnd];
+
+#[derive(Default)]
+pub struct Scheduler {
+    events: BinaryHeap<Reverse<(u128, EventKind)>>,
+}
+
+pub fn init() -> Scheduler {
+    let mut scheduler = Scheduler::default();
+    scheduler.schedule_ppu(0);
+    scheduler
+}
+
+impl Scheduler {
+    pub fn schedule(&mut self, time: u128, kind: EventKind) {
+        self.events.push(Reverse((time, kind)));
+    }
+
+    // Clock of the earliest pending event
+    pub fn next_due(&self) -> Option<u128> {
+        self.events.peek().map(|Reverse((time, _))| *time)
+    }
+
+    pub fn pop_due(&mut self, clock: u128) -> Option<(u128, EventKind)> {
+        match self.next_due() {
+            Some(time) if time <= clock => self.events.pop().map(|Reverse(event)| event),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    // Schedules each PPU event on its next occurrence at or after `clock`
+    fn schedule_ppu(&mut self, clock: u128) {
+        for event in PPU_EVENTS {
+            let offset = event.line_offset();
+            let line = clock - clock % CYCLES_PER_LINE;
+            let time = match line + offset >= clock {
+                true => line + offset,
+                false => line + offset + CYCLES_PER_LINE,
+            };
+            self.schedule(time, EventKind::Ppu(event));
+        }
+    }
+}
+
+impl GameBoy {
+    pub(crate) fn run_due_events(&mut self) {
+        while let Some((time, kind)) = self.scheduler.pop_due(self.clock) {
+            self.memory.interrupts_stale = true;
+            match kind {
+                EventKind::SerialBit => self.serial_bit(time),
+                EventKind::Ppu(event) => {
+                    self.renderer(event);
+                    // once per line, skipping any lines missed while test_mode was set
+                    let lines = (self.clock - time) / CYCLES_PER_LINE + 1;
+                    self.scheduler.schedule(time + lines * CYCLES_PER_LINE, kind);
+                }
+            }
+        }
+    }
+
+    // Rebuilds the event queue after the clock or the subsystems were restored
+    pub(crate) fn reschedule_events(&mut self) {
+        self.scheduler.clear();
+        self.scheduler.schedule_ppu(self.clock);
+        self.memory.interrupts_stale = true;
+        if let Some(time) = self.serial.next_bit_due(self.memory.read(0xFF02)) {
+            self.scheduler.schedule(time, EventKind::SerialBit);
+        }
+    }
+
+    // Skips up to `limit` idle M-cycles at once when nothing could happen during them: the CPU
+    // is partway through an instruction, no interrupt is about to be dispatched, no event is due
+    // and no serial device needs polling. Returns the number of cycles skipped, which may be 0.
+    // The instruction's last idle cycle is always left to tick().
+    pub(crate) fn skip_idle_cycles(&mut self, limit: u64) -> u64 {
+        let idle = match self.cycles_to_idle {
+            Some(idle) if self.running && idle > 1 => idle as u64 - 1,
+            _ => return 0,
+        };
+        if self.isr.state != IsrState::None || self.ime_dispatch.is_some() {
+            return 0;
+        }
+        if !self.test_mode {
+            // what the next tick would do first, so it's safe to call early
+            self.update_interrupt_requests();
+            if self.ime && (self.get_ie() & self.get_if()) != 0 {
+                return 0;
+            }
+            if self.serial_needs_polling() {
+                return 0;
+            }
+        }
+        let until_event = match (self.test_mode, self.scheduler.next_due()) {
+            (false, Some(time)) => time.saturating_sub(self.clock).min(u64::MAX as u128) as u64,
+            _ => u64::MAX,
+        };
+        let cycles = idle.min(until_event).min(limit);
+        if cycles == 0 {
+            return 0;
+        }
+        self.cycles_to_idle = self.cycles_to_idle.map(|idle| idle - cycles as u8);
+        if let Some(player) = self.gbs.as_mut() {
+            player.skip_cycles(cycles as u32);
+        }
+        self.clock += cycles as u128;
+        cycles
+    }
+
+    // Advances by skipping idle cycles where possible, otherwise by one tick.
+    // Returns the M-cycles advanced.
+    pub(crate) fn advance(&mut self, limit: u64) -> u64 {
+        match self.skip_idle_cycles(limit) {
+            0 => {
+                self.tick();
+                1
+            }
+            cycles => cycles,
+        }
+    }
+}
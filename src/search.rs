@@ -0,0 +1,106 @@
+// RAM search, as in classic cheat finders: snapshot cart RAM, WRAM and HRAM, then narrow the
+// candidates down with filters comparing each one's current value to the last snapshot.
+//   let mut search = RamSearch::new(&gameboy, Width::Byte);
+//   ... lose a life ...
+//   search.filter(&gameboy, Filter::Decreased);
+//   search.filter(&gameboy, Filter::Value(2));
+// 16-bit values are little-endian, and don't straddle two regions.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use std::ops::RangeInclusive;
+
+const REGIONS: [RangeInclusive<u16>; 3] = [0xA000..=0xBFFF, 0xC000..=0xDFFF, 0xFF80..=0xFFFE];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u16),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    pub address: u16,
+    pub previous: u16, // at the last snapshot
+    pub value: u16,
+}
+
+pub struct RamSearch {
+    pub width: Width,
+    candidates: Vec<(u16, u16)>, // address and value at the last snapshot
+}
+
+impl Filter {
+    pub fn keeps(&self, previous: u16, value: u16) -> bool {
+        match self {
+            Filter::Unchanged => value == previous,
+            Filter::Changed => value != previous,
+            Filter::Increased => value > previous,
+            Filter::Decreased => value < previous,
+            Filter::Value(wanted) => value == *wanted,
+        }
+    }
+}
+
+impl RamSearch {
+    // Every address in the searched regions is a candidate to start with
+    pub fn new(gameboy: &GameBoy, width: Width) -> RamSearch {
+        let last_offset = match width {
+            Width::Byte => 0,
+            Width::Word => 1,
+        };
+        let candidates = REGIONS
+            .iter()
+            .flat_map(|region| *region.start()..=region.end() - last_offset)
+            .map(|address| (address, read(gameboy, address, width)))
+            .collect();
+        RamSearch { width, candidates }
+    }
+
+    // Keeps the candidates the filter holds for and snapshots their values, returning how many are left
+    pub fn filter(&mut self, gameboy: &GameBoy, filter: Filter) -> usize {
+        let width = self.width;
+        self.candidates.retain_mut(|(address, previous)| {
+            let value = read(gameboy, *address, width);
+            let keep = filter.keeps(*previous, value);
+            *previous = value;
+            keep
+        });
+        self.candidates.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    pub fn results(&self, gameboy: &GameBoy) -> Vec<Candidate> {
+        self.candidates
+            .iter()
+            .map(|(address, previous)| Candidate {
+                address: *address,
+                previous: *previous,
+                value: read(gameboy, *address, self.width),
+            })
+            .collect()
+    }
+}
+
+fn read(gameboy: &GameBoy, address: u16, width: Width) -> u16 {
+    let low = gameboy.memory.read(address) as u16;
+    match width {
+        Width::Byte => low,
+        Width::Word => low | (gameboy.memory.read(address + 1) as u16) << 8,
+    }
+}
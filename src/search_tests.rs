@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod search_test {
+    use crate::gb;
+    use crate::search::{Candidate, Filter, RamSearch, Width};
+
+    #[test]
+    fn narrows_down_bytes_and_words() {
+        let mut gameboy = gb::init();
+        gameboy.memory.main[0xC123] = 5;
+        gameboy.memory.main[0xFF90] = 7;
+        let mut search = RamSearch::new(&gameboy, Width::Byte);
+        assert_eq!(search.len(), 0x2000 + 0x2000 + 0x7F);
+
+        gameboy.memory.main[0xC123] = 4;
+        gameboy.memory.main[0xFF90] = 9;
+        assert_eq!(search.filter(&gameboy, Filter::Changed), 2);
+        assert_eq!(search.filter(&gameboy, Filter::Unchanged), 2);
+        assert_eq!(search.filter(&gameboy, Filter::Value(9)), 1);
+        assert_eq!(search.results(&gameboy), [Candidate { address: 0xFF90, previous: 9, value: 9 }]);
+
+        gameboy.memory.main[0xC200] = 0x34;
+        gameboy.memory.main[0xC201] = 0x12;
+        let mut search = RamSearch::new(&gameboy, Width::Word);
+        assert_eq!(search.results(&gameboy).last().unwrap().address, 0xFFFD);
+        gameboy.memory.main[0xC200] = 0x00;
+        gameboy.memory.main[0xC201] = 0x13;
+        // the word at C201 overlaps the change and increased too
+        assert_eq!(search.filter(&gameboy, Filter::Increased), 2);
+        assert_eq!(search.filter(&gameboy, Filter::Value(0x1300)), 1);
+        assert_eq!(search.results(&gameboy)[0].address, 0xC200);
+        assert_eq!(search.filter(&gameboy, Filter::Decreased), 0);
+        assert!(search.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// Serial port: SB (0xFF01) holds the byte being shifted, SC (0xFF02) starts a transfer
+// (bit 7) and selects the clock source (bit 0, 1 = internal).
+// Bits are shifted out MSB first while the peer's bits are shifted in at the bottom.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use crate::scheduler::EventKind;
+
+pub const SERIAL_CYCLES_PER_BIT: u8 = 128; // 8192 Hz internal clock, in M-cycles
+
+pub trait SerialDevice {
+    // Called when this Game Boy is clock master: `out` is the bit shifted out,
+    // the return value is the bit the peer shifts back in.
+    fn exchange_bit(&mut self, out: bool) -> bool;
+
+    // Called every M-cycle while this Game Boy is not driving the clock.
+    // `out` is the bit that would be shifted out if a transfer is waiting on the external
+    // clock, or None if no transfer is enabled. Returns the incoming bit if the peer
+    // supplied a clock pulse that shifted it in.
+    fn poll_external_clock(&mut self, _out: Option<bool>) -> Option<bool> {
+        None
+    }
+}
+
+pub struct Serial {
+    pub device: Option<Box<dyn SerialDevice>>,
+    pub capture: Option<String>, // every byte sent, when capture is enabled
+    bits_remaining: u8,
+    next_bit: u128, // clock of the next internally clocked shift
+    outgoing: u8,
+    polling: bool, // see GameBoy::serial_needs_polling()
+}
+
+pub fn init() -> Serial {
+    Serial {
+        device: None,
+        capture: None,
+        bits_remaining: 0,
+        next_bit: 0,
+        outgoing: 0,
+        polling: false,
+    }
+}
+
+// Transfer progress for save states, the attached device and capture are left alone
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct SerialState {
+    bits_remaining: u8,
+    cycles_to_bit: u8,
+    outgoing: u8,
+}
+
+impl Serial {
+    // cycles_to_bit counts down to the next shift, including the tick that shifts
+    pub(crate) fn save_state(&self, clock: u128) -> SerialState {
+        SerialState {
+            bits_remaining: self.bits_remaining,
+            cycles_to_bit: (self.next_bit + 1).saturating_sub(clock).min(SERIAL_CYCLES_PER_BIT as u128) as u8,
+            outgoing: self.outgoing,
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: SerialState, clock: u128) {
+        self.bits_remaining = state.bits_remaining;
+        self.next_bit = (clock + state.cycles_to_bit as u128).saturating_sub(1);
+        self.outgoing = state.outgoing;
+    }
+
+    // For states that don't record transfer progress. A transfer SC still enables starts over.
+    pub(crate) fn reset_transfer(&mut self) {
+        self.bits_remaining = 0;
+        self.outgoing = 0;
+    }
+
+    // When the next internally clocked shift is due, if a transfer is running on it
+    pub(crate) fn next_bit_due(&self, sc: u8) -> Option<u128> {
+        match self.bits_remaining > 0 && sc & 0x81 == 0x81 {
+            true => Some(self.next_bit),
+            false => None,
+        }
+    }
+
+    // True if serial_tick has nothing to do until the next scheduled shift
+    pub(crate) fn is_quiet(&self, sc: u8, clock: u128) -> bool {
+        match sc & 0x80 {
+            0 => self.device.is_none() && self.bits_remaining == 0,
+            _ => self.bits_remaining > 0 && sc & 1 != 0 && self.next_bit >= clock,
+        }
+    }
+}
+
+impl GameBoy {
+    pub fn attach_serial_device(&mut self, device: Box<dyn SerialDevice>) {
+        self.serial.device = Some(device);
+        self.memory.serial_stale = true;
+    }
+
+    pub fn detach_serial_device(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.memory.serial_stale = true;
+        self.serial.device.take()
+    }
+
+    // Start accumulating sent bytes, e.g. the results Blargg's test ROMs print.
+    pub fn capture_serial(&mut self) {
+        if self.serial.capture.is_none() {
+            self.serial.capture = Some(String::new());
+        }
+    }
+
+    pub fn serial_output(&self) -> &str {
+        self.serial.capture.as_deref().unwrap_or("")
+    }
+
+    // Runs until the captured serial output contains one of `needles`, returning its index,
+    // or None if `max_cycles` M-cycles pass first.
+    pub fn run_until_serial_contains(&mut self, needles: &[&str], max_cycles: u64) -> Option<usize> {
+        self.capture_serial();
+        let mut checked_len = usize::MAX;
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            cycles += self.advance(max_cycles - cycles);
+            let output = self.serial_output();
+            if output.len() != checked_len {
+                checked_len = output.len();
+                if let Some(index) = needles.iter().position(|needle| output.contains(needle)) {
+                    return Some(index);
+                }
+            }
+        }
+        None
+    }
+
+    // Whether serial_tick has anything to do. That only changes when SC is written, a device is
+    // attached or detached or serial_tick itself runs, so SC isn't read again in between.
+    pub(crate) fn serial_needs_polling(&mut self) -> bool {
+        if self.memory.serial_stale {
+            self.memory.serial_stale = false;
+            self.serial.polling = !self.serial.is_quiet(self.memory.read(0xFF02), self.clock);
+        }
+        self.serial.polling
+    }
+
+    // Starts transfers and polls the device while it drives the clock. Internally clocked
+    // shifts are scheduled events, see serial_bit.
+    pub(crate) fn serial_tick(&mut self) {
+        let sc = self.memory.read(0xFF02);
+        if sc & 0x80 == 0 {
+            self.serial.bits_remaining = 0;
+            if let Some(device) = self.serial.device.as_mut() {
+                device.poll_external_clock(None);
+            }
+            return;
+        }
+        if self.serial.bits_remaining == 0 {
+            // transfer just requested
+            self.serial.bits_remaining = 8;
+            self.serial.outgoing = self.memory.read(0xFF01);
+            if sc & 1 != 0 {
+                self.schedule_serial_bit();
+            }
+        }
+
+        if sc & 1 != 0 {
+            if self.serial.next_bit < self.clock {
+                // the clock source was switched back to internal mid-transfer
+                self.schedule_serial_bit();
+            }
+            return;
+        }
+        let out = self.memory.read(0xFF01) & 0x80 != 0;
+        if let Some(bit) = self.serial.device.as_mut().and_then(|device| device.poll_external_clock(Some(out))) {
+            self.shift_serial(bit);
+        }
+        // otherwise still waiting on the link partner
+    }
+
+    fn schedule_serial_bit(&mut self) {
+        self.serial.next_bit = self.clock + SERIAL_CYCLES_PER_BIT as u128 - 1;
+        self.scheduler.schedule(self.serial.next_bit, EventKind::SerialBit);
+    }
+
+    pub(crate) fn serial_bit(&mut self, time: u128) {
+        let sc = self.memory.read(0xFF02);
+        if self.serial.next_bit_due(sc) != Some(time) {
+            return; // the transfer was cancelled or restarted since this was scheduled
+        }
+        let out = self.memory.read(0xFF01) & 0x80 != 0;
+        let incoming = match self.serial.device.as_mut() {
+            Some(device) => device.exchange_bit(out),
+            None => true, // nothing connected, the line floats high
+        };
+        self.shift_serial(incoming);
+        if self.serial.bits_remaining > 0 {
+            self.serial.next_bit = time + SERIAL_CYCLES_PER_BIT as u128;
+            self.scheduler.schedule(self.serial.next_bit, EventKind::SerialBit);
+        }
+    }
+
+    fn shift_serial(&mut self, incoming: bool) {
+        let sb = self.memory.read(0xFF01);
+        self.memory.write(0xFF01, (sb << 1) | incoming as u8);
+        self.serial.bits_remaining -= 1;
+        if self.serial.bits_remaining == 0 {
+            if let Some(capture) = self.serial.capture.as_mut() {
+                capture.push(self.serial.outgoing as char);
+            }
+            let sc = self.memory.read(0xFF02);
+            self.memory.write(0xFF02, sc & 0x7F);
+            self.set_if(self.get_if() | 0b1000);
+        }
+    }
+}
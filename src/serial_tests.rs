@@ -0,0 +1,226 @@
+#[cfg(test)]
+mod serial_test {
+    use crate::gb;
+    use crate::link;
+    use crate::memory::Memory;
+    use crate::printer::{self, Printer};
+    use crate::serial::{SerialDevice, SERIAL_CYCLES_PER_BIT};
+    use crate::socket_link::SocketLink;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Shifts `reply` back MSB first and records what it received
+    struct ShiftDevice {
+        reply: u8,
+        received: Rc<RefCell<u8>>,
+        external: bool,
+    }
+
+    impl SerialDevice for ShiftDevice {
+        fn exchange_bit(&mut self, out: bool) -> bool {
+            let bit = self.reply & 0x80 != 0;
+            self.reply <<= 1;
+            let mut received = self.received.borrow_mut();
+            *received = (*received << 1) | out as u8;
+            bit
+        }
+
+        fn poll_external_clock(&mut self, out: Option<bool>) -> Option<bool> {
+            match out {
+                Some(out) if self.external => Some(self.exchange_bit(out)),
+                _ => None,
+            }
+        }
+    }
+
+    fn idle_gameboy() -> gb::GameBoy {
+        let mut gameboy = gb::init();
+        gameboy.memory.main[0xFF50] = 1;
+        gameboy.memory.main[0xC000] = 0x18; // JR -2
+        gameboy.memory.main[0xC001] = 0xFE;
+        gameboy.registers.pc = 0xC000;
+        gameboy
+    }
+
+    #[test]
+    fn internal_clock_transfer() {
+        let mut gameboy = idle_gameboy();
+        let received = Rc::new(RefCell::new(0));
+        gameboy.attach_serial_device(Box::new(ShiftDevice { reply: 0x3C, received: received.clone(), external: false }));
+        gameboy.memory.write(0xFF01, 0xA5);
+        gameboy.memory.write(0xFF02, 0x81);
+
+        for _ in 0..(8 * SERIAL_CYCLES_PER_BIT as u32 - 1) {
+            gameboy.tick();
+        }
+        assert_ne!(gameboy.memory.read(0xFF02) & 0x80, 0, "transfer finished early");
+        gameboy.tick();
+        assert_eq!(gameboy.memory.read(0xFF02) & 0x80, 0);
+        assert_ne!(gameboy.memory.read(0xFF0F) & 0b1000, 0, "serial interrupt not requested");
+        assert_eq!(gameboy.memory.read(0xFF01), 0x3C);
+        assert_eq!(*received.borrow(), 0xA5);
+    }
+
+    #[test]
+    fn no_device_shifts_in_ones() {
+        let mut gameboy = idle_gameboy();
+        gameboy.memory.write(0xFF01, 0x00);
+        gameboy.memory.write(0xFF02, 0x81);
+        for _ in 0..(8 * SERIAL_CYCLES_PER_BIT as u32) {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.memory.read(0xFF01), 0xFF);
+    }
+
+    #[test]
+    fn external_clock_waits_for_partner() {
+        let mut gameboy = idle_gameboy();
+        gameboy.memory.write(0xFF01, 0x42);
+        gameboy.memory.write(0xFF02, 0x80);
+        for _ in 0..10000 {
+            gameboy.tick();
+        }
+        assert_ne!(gameboy.memory.read(0xFF02) & 0x80, 0, "transfer completed without a clock");
+
+        let received = Rc::new(RefCell::new(0));
+        gameboy.attach_serial_device(Box::new(ShiftDevice { reply: 0x99, received: received.clone(), external: true }));
+        for _ in 0..8 {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.memory.read(0xFF02) & 0x80, 0);
+        assert_eq!(gameboy.memory.read(0xFF01), 0x99);
+        assert_eq!(*received.borrow(), 0x42);
+    }
+
+    #[test]
+    fn link_cable_exchanges_bytes() {
+        let mut cable = link::connect(idle_gameboy(), idle_gameboy());
+        cable.second.memory.write(0xFF01, 0xC3);
+        cable.second.memory.write(0xFF02, 0x80); // slave, waits on the external clock
+        cable.run_cycles(1000);
+        cable.first.memory.write(0xFF01, 0x5A);
+        cable.first.memory.write(0xFF02, 0x81); // master
+        cable.run_cycles(8 * SERIAL_CYCLES_PER_BIT as u64 + 1);
+
+        let (first, second) = cable.disconnect();
+        assert_eq!(first.memory.read(0xFF01), 0xC3);
+        assert_eq!(second.memory.read(0xFF01), 0x5A);
+        assert_eq!(first.memory.read(0xFF02) & 0x80, 0);
+        assert_eq!(second.memory.read(0xFF02) & 0x80, 0);
+        assert_ne!(second.memory.read(0xFF0F) & 0b1000, 0);
+    }
+
+    #[test]
+    fn link_cable_master_reads_ones_without_slave_transfer() {
+        let mut cable = link::connect(idle_gameboy(), idle_gameboy());
+        cable.second.memory.write(0xFF01, 0x00);
+        cable.first.memory.write(0xFF01, 0x00);
+        cable.first.memory.write(0xFF02, 0x81);
+        cable.run_cycles(8 * SERIAL_CYCLES_PER_BIT as u64);
+        assert_eq!(cable.first.memory.read(0xFF01), 0xFF);
+        assert_eq!(cable.second.memory.read(0xFF01), 0x00);
+    }
+
+    fn transfer_over(link: SocketLink, sb: u8, sc: u8) -> u8 {
+        let mut gameboy = idle_gameboy();
+        gameboy.attach_serial_device(Box::new(link));
+        gameboy.memory.write(0xFF01, sb);
+        gameboy.memory.write(0xFF02, sc);
+        for _ in 0..1_000_000 {
+            gameboy.tick();
+            if gameboy.memory.read(0xFF02) & 0x80 == 0 {
+                break;
+            }
+        }
+        assert_eq!(gameboy.memory.read(0xFF02) & 0x80, 0, "transfer did not complete");
+        gameboy.memory.read(0xFF01)
+    }
+
+    #[test]
+    fn socket_link_over_tcp() {
+        let slave = SocketLink::listen_tcp("127.0.0.1:0").unwrap();
+        let address = slave.local_addr().unwrap();
+        let slave_thread = std::thread::spawn(move || transfer_over(slave, 0xC3, 0x80));
+        let master = SocketLink::connect_tcp(address).unwrap();
+        assert_eq!(transfer_over(master, 0x5A, 0x81), 0xC3);
+        assert_eq!(slave_thread.join().unwrap(), 0x5A);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_link_over_unix_socket() {
+        let path = std::env::temp_dir().join(format!("dmg-link-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let slave = SocketLink::listen_unix(&path).unwrap();
+        let slave_thread = std::thread::spawn(move || transfer_over(slave, 0x12, 0x80));
+        let master = SocketLink::connect_unix(&path).unwrap();
+        assert_eq!(transfer_over(master, 0x34, 0x81), 0x12);
+        assert_eq!(slave_thread.join().unwrap(), 0x34);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn send_byte(printer: &mut Printer, byte: u8) -> u8 {
+        let mut reply = 0;
+        for bit in (0..8).rev() {
+            reply = (reply << 1) | printer.exchange_bit((byte >> bit) & 1 != 0) as u8;
+        }
+        reply
+    }
+
+    // Returns the (alive, status) replies
+    fn send_packet(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        let checksum = packet.iter().fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+        send_byte(printer, 0x88);
+        send_byte(printer, 0x33);
+        for byte in packet {
+            send_byte(printer, byte);
+        }
+        send_byte(printer, checksum as u8);
+        send_byte(printer, (checksum >> 8) as u8);
+        let alive = send_byte(printer, 0x00);
+        let status = send_byte(printer, 0x00);
+        (alive, status)
+    }
+
+    #[test]
+    fn printer_prints_a_band() {
+        let (mut printer, output) = printer::init();
+        assert_eq!(send_packet(&mut printer, 0x01, false, &[]), (0x81, 0x00));
+
+        // every tile row is colour 1: 0xFF low plane, 0x00 high plane, RLE encoded
+        let mut data = Vec::new();
+        for _ in 0..(0x280 / 2) {
+            data.extend_from_slice(&[0x01, 0xFF, 0x00]);
+        }
+        assert_eq!(printer::decompress(&[0x81, 0xAB, 0x01, 0x01, 0x02]), vec![0xAB, 0xAB, 0xAB, 0x01, 0x02]);
+        let (alive, status) = send_packet(&mut printer, 0x04, true, &data[..]);
+        assert_eq!(alive, 0x81);
+        assert_eq!(status & 0x08, 0x08, "data should be unprocessed");
+        send_packet(&mut printer, 0x04, false, &[]);
+
+        let (_, status) = send_packet(&mut printer, 0x02, false, &[0x01, 0x01, 0xE4, 0x40]);
+        assert_eq!(status & 0x02, 0x02, "printer should be busy");
+        let pages = output.take_pages();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].height, 16 + 16);
+        assert_eq!(pages[0].pixels[0], 1);
+        assert_eq!(pages[0].pixels[16 * 160], 0, "margin should be blank");
+
+        let path = std::env::temp_dir().join(format!("dmg-printer-{}.png", std::process::id()));
+        pages[0].save_png(&path).unwrap();
+        let png = std::fs::read(&path).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        let _ = std::fs::remove_file(&path);
+
+        for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00] {
+            send_byte(&mut printer, byte); // STATUS with a checksum of 0 instead of 0x0F
+        }
+        assert_eq!(send_byte(&mut printer, 0x00) & 0x01, 0x01, "bad checksum should be flagged");
+
+        let (mut printer, _) = printer::init();
+        let (_, status) = send_packet(&mut printer, 0x04, false, &[0xFF; 0x280]); // the checksum wraps
+        assert_eq!(status & 0x01, 0x00);
+    }
+}
@@ -0,0 +1,275 @@
+// Link cable tunnelled over a TCP or Unix domain socket, so two emulator processes can talk.
+//
+// Every message is two bytes, a tag and a value:
+//   HELLO version  sent by both sides when a connection is made
+//   CLOCK bit      the clock master shifted `bit` out and waits for a REPLY
+//   REPLY bit      the bit the other side shifted back (1 if it had no transfer enabled)
+//
+// The master stalls emulation until the REPLY arrives instead of guessing and rolling back,
+// so both machines always see the same bits. If the peer goes away the master reads 1s,
+// like an unplugged cable, and the link keeps trying to reconnect in the background.
+use crate::serial::SerialDevice;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const MSG_HELLO: u8 = 0x00;
+const MSG_CLOCK: u8 = 0x01;
+const MSG_REPLY: u8 = 0x02;
+const PROTOCOL_VERSION: u8 = 1;
+
+const POLL_INTERVAL: u32 = 32; // M-cycles between socket reads while waiting on the peer's clock
+const RECONNECT_INTERVAL: u32 = 1 << 16; // M-cycles between reconnect attempts
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(50); // longest a background reconnect stalls
+
+enum Endpoint {
+    TcpListen(TcpListener),
+    TcpConnect(SocketAddr),
+    #[cfg(unix)]
+    UnixListen(UnixListener),
+    #[cfg(unix)]
+    UnixConnect(PathBuf),
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buffer),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buffer),
+        }
+    }
+
+    fn write_all(&mut self, buffer: &[u8]) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.write_all(buffer),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write_all(buffer),
+        }
+    }
+}
+
+pub struct SocketLink {
+    endpoint: Endpoint,
+    stream: Option<Stream>,
+    inbox: Vec<u8>,
+    pub timeout: Duration, // how long the master stalls for a REPLY before dropping the connection
+    polls_until_read: u32,
+    polls_until_reconnect: u32,
+}
+
+impl SocketLink {
+    fn new(endpoint: Endpoint) -> SocketLink {
+        let mut link = SocketLink {
+            endpoint,
+            stream: None,
+            inbox: Vec::new(),
+            timeout: Duration::from_secs(1),
+            polls_until_read: 0,
+            polls_until_reconnect: 0,
+        };
+        link.reconnect(Instant::now() + CONNECT_TIMEOUT);
+        link
+    }
+
+    pub fn listen_tcp(address: impl ToSocketAddrs) -> io::Result<SocketLink> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(SocketLink::new(Endpoint::TcpListen(listener)))
+    }
+
+    // The peer doesn't need to be up yet, the link connects once it is
+    pub fn connect_tcp(address: impl ToSocketAddrs) -> io::Result<SocketLink> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address to connect to"))?;
+        Ok(SocketLink::new(Endpoint::TcpConnect(address)))
+    }
+
+    #[cfg(unix)]
+    pub fn listen_unix(path: impl AsRef<Path>) -> io::Result<SocketLink> {
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(SocketLink::new(Endpoint::UnixListen(listener)))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> io::Result<SocketLink> {
+        Ok(SocketLink::new(Endpoint::UnixConnect(path.as_ref().to_path_buf())))
+    }
+
+    // Address a TCP listener is bound to, useful after binding to port 0
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.endpoint {
+            Endpoint::TcpListen(listener) => listener.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // Connecting over TCP gives up at the deadline rather than stalling until the peer answers
+    fn reconnect(&mut self, deadline: Instant) -> bool {
+        let stream = match &self.endpoint {
+            Endpoint::TcpListen(listener) => listener.accept().ok().map(|(stream, _)| stream).map(Stream::Tcp),
+            Endpoint::TcpConnect(address) => {
+                let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+                TcpStream::connect_timeout(address, remaining).ok().map(Stream::Tcp)
+            }
+            #[cfg(unix)]
+            Endpoint::UnixListen(listener) => listener.accept().ok().map(|(stream, _)| stream).map(Stream::Unix),
+            #[cfg(unix)]
+            Endpoint::UnixConnect(path) => UnixStream::connect(path).ok().map(Stream::Unix),
+        };
+        let stream = match stream {
+            Some(stream) => stream,
+            None => return false,
+        };
+        if let Stream::Tcp(tcp) = &stream {
+            let _ = tcp.set_nodelay(true);
+        }
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        self.stream = Some(stream);
+        self.inbox.clear();
+        self.send(MSG_HELLO, PROTOCOL_VERSION);
+        self.stream.is_some()
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.inbox.clear();
+        self.polls_until_reconnect = RECONNECT_INTERVAL;
+    }
+
+    fn send(&mut self, tag: u8, value: u8) {
+        if let Some(stream) = self.stream.as_mut() {
+            if stream.write_all(&[tag, value]).is_err() {
+                self.disconnect();
+            }
+        }
+    }
+
+    // Reads whatever has arrived. With a deadline, blocks until something arrives or it passes.
+    fn receive(&mut self, deadline: Option<Instant>) {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+            if stream.set_nonblocking(false).is_err() || stream.set_read_timeout(Some(remaining)).is_err() {
+                self.disconnect();
+                return;
+            }
+        }
+        let mut buffer = [0u8; 64];
+        let result = stream.read(&mut buffer);
+        if deadline.is_some() && stream.set_nonblocking(true).is_err() {
+            self.disconnect();
+            return;
+        }
+        match result {
+            Ok(0) => self.disconnect(), // peer hung up
+            Ok(length) => self.inbox.extend_from_slice(&buffer[..length]),
+            Err(error) if error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut => (),
+            Err(error) if error.kind() == ErrorKind::Interrupted => (),
+            Err(_) => self.disconnect(),
+        }
+    }
+
+    fn next_message(&mut self) -> Option<(u8, u8)> {
+        if self.inbox.len() < 2 {
+            return None;
+        }
+        let message = (self.inbox[0], self.inbox[1]);
+        self.inbox.drain(..2);
+        if message.0 == MSG_HELLO && message.1 != PROTOCOL_VERSION {
+            self.disconnect();
+            return None;
+        }
+        Some(message)
+    }
+}
+
+impl SerialDevice for SocketLink {
+    fn exchange_bit(&mut self, out: bool) -> bool {
+        let deadline = Instant::now() + self.timeout;
+        if self.stream.is_none() && !self.reconnect(deadline) {
+            return true;
+        }
+        self.send(MSG_CLOCK, out as u8);
+        while self.stream.is_some() {
+            while let Some((tag, value)) = self.next_message() {
+                match tag {
+                    MSG_REPLY => return value != 0,
+                    // the peer is clocking too, it gets 1s as we aren't listening to its clock
+                    MSG_CLOCK => self.send(MSG_REPLY, 1),
+                    _ => (),
+                }
+            }
+            if Instant::now() >= deadline {
+                self.disconnect();
+                break;
+            }
+            self.receive(Some(deadline));
+        }
+        true
+    }
+
+    fn poll_external_clock(&mut self, out: Option<bool>) -> Option<bool> {
+        if self.stream.is_none() {
+            if self.polls_until_reconnect > 0 {
+                self.polls_until_reconnect -= 1;
+            } else if !self.reconnect(Instant::now() + CONNECT_TIMEOUT) {
+                self.polls_until_reconnect = RECONNECT_INTERVAL;
+            }
+            return None;
+        }
+        if self.polls_until_read > 0 && self.inbox.is_empty() {
+            self.polls_until_read -= 1;
+            return None;
+        }
+        self.polls_until_read = POLL_INTERVAL;
+        self.receive(None);
+        while let Some((tag, value)) = self.next_message() {
+            if tag == MSG_CLOCK {
+                self.send(MSG_REPLY, out.unwrap_or(true) as u8);
+                if out.is_some() {
+                    return Some(value != 0);
+                }
+            }
+        }
+        None
+    }
+}
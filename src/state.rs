@@ -0,0 +1,237 @@
+// Save states.
+//
+// Layout: the magic "DMGSTATE", the format version (LE u16), then chunks up to an END chunk.
+// Each chunk is a 4 byte tag, the payload length (LE u32) and a bincode encoded payload, so
+// sections can be skipped, added for new peripherals, or migrated independently.
+// The cartridge ROM and any attached serial device are not part of the state. Cheats are, as
+// they change what the game sees.
+use crate::cheats::Cheats;
+use crate::gb::{GameBoy, Isr, Registers};
+use crate::gbs::GbsState;
+use crate::memory::MappingType;
+use crate::serial::SerialState;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub const STATE_MAGIC: &[u8; 8] = b"DMGSTATE";
+pub const STATE_VERSION: u16 = 1;
+
+const CHUNK_CPU: &[u8; 4] = b"CPU ";
+const CHUNK_MEMORY: &[u8; 4] = b"MEM ";
+const CHUNK_ISR: &[u8; 4] = b"ISR ";
+const CHUNK_PPU: &[u8; 4] = b"PPU ";
+const CHUNK_SERIAL: &[u8; 4] = b"SER ";
+const CHUNK_GBS: &[u8; 4] = b"GBS ";
+const CHUNK_JOYPAD: &[u8; 4] = b"JOYP";
+const CHUNK_COUNTERS: &[u8; 4] = b"CNT ";
+const CHUNK_CHEATS: &[u8; 4] = b"CHTS";
+const CHUNK_END: &[u8; 4] = b"END ";
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    MissingChunk([u8; 4]),
+    BadChunk([u8; 4]),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CpuState {
+    registers: Registers,
+    clock: u128,
+    running: bool,
+    cycles_to_idle: Option<u8>,
+    ime: bool,
+    ime_dispatch: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MemoryState {
+    mapping_type: MappingType,
+    main: Vec<u8>,
+    boot_rom: Vec<u8>,
+    rom_bank: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Counters {
+    frame_count: u64,
+    instructions: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PpuState {
+    window_line_counter: u8,
+    display_temp: Vec<u8>,
+    display: Vec<u8>,
+}
+
+type Chunks<'a> = Vec<([u8; 4], &'a [u8])>;
+
+fn write_chunk<T: Serialize>(output: &mut Vec<u8>, tag: &[u8; 4], section: &T) {
+    let payload = bincode::serialize(section).expect("Could not serialise save state section");
+    output.extend_from_slice(tag);
+    output.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    output.extend_from_slice(&payload);
+}
+
+fn read_chunks(data: &[u8]) -> Result<(u16, Chunks<'_>), StateError> {
+    if data.len() < 10 {
+        return Err(StateError::Truncated);
+    }
+    if &data[0..8] != STATE_MAGIC {
+        return Err(StateError::BadMagic);
+    }
+    let version = u16::from_le_bytes([data[8], data[9]]);
+    let mut chunks = Vec::new();
+    let mut offset = 10;
+    loop {
+        if data.len() < offset + 8 {
+            return Err(StateError::Truncated);
+        }
+        let tag: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+        let length = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        offset += 8;
+        if &tag == CHUNK_END {
+            return Ok((version, chunks));
+        }
+        if data.len() < offset + length {
+            return Err(StateError::Truncated);
+        }
+        chunks.push((tag, &data[offset..offset + length]));
+        offset += length;
+    }
+}
+
+// Upgrades chunks written by older format versions to the current layout, one version at a time.
+// There are no older versions yet: add a step here whenever a section's layout changes. New
+// optional chunks don't need one, as states without them still load.
+fn migrate(version: u16, chunks: Chunks<'_>) -> Result<Chunks<'_>, StateError> {
+    match version {
+        STATE_VERSION => Ok(chunks),
+        _ => Err(StateError::UnsupportedVersion(version)),
+    }
+}
+
+fn decode<T: DeserializeOwned>(chunks: &Chunks<'_>, tag: &[u8; 4]) -> Result<Option<T>, StateError> {
+    match chunks.iter().find(|(chunk_tag, _)| chunk_tag == tag) {
+        Some((_, payload)) => bincode::deserialize(payload).map(Some).map_err(|_| StateError::BadChunk(*tag)),
+        None => Ok(None),
+    }
+}
+
+fn require<T>(section: Option<T>, tag: &[u8; 4]) -> Result<T, StateError> {
+    section.ok_or(StateError::MissingChunk(*tag))
+}
+
+impl GameBoy {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(STATE_MAGIC);
+        output.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        write_chunk(&mut output, CHUNK_CPU, &CpuState {
+            registers: self.registers.clone(),
+            clock: self.clock,
+            running: self.running,
+            cycles_to_idle: self.cycles_to_idle,
+            ime: self.ime,
+            ime_dispatch: self.ime_dispatch,
+        });
+        write_chunk(&mut output, CHUNK_MEMORY, &MemoryState {
+            mapping_type: self.memory.mapping_type,
+            main: self.memory.main.to_vec(),
+            boot_rom: self.memory.rom.to_vec(),
+            rom_bank: self.memory.rom_bank,
+        });
+        write_chunk(&mut output, CHUNK_ISR, &self.isr);
+        write_chunk(&mut output, CHUNK_PPU, &PpuState {
+            window_line_counter: self.window_line_counter,
+            display_temp: self.display_temp.to_vec(),
+            display: self.display.to_vec(),
+        });
+        write_chunk(&mut output, CHUNK_SERIAL, &self.serial.save_state(self.clock));
+        if let Some(player) = self.gbs.as_ref() {
+            write_chunk(&mut output, CHUNK_GBS, &player.save_state());
+        }
+        write_chunk(&mut output, CHUNK_JOYPAD, &self.memory.joypad);
+        write_chunk(&mut output, CHUNK_COUNTERS, &Counters {
+            frame_count: self.frame_count,
+            instructions: self.instructions,
+        });
+        write_chunk(&mut output, CHUNK_CHEATS, &self.memory.cheats);
+        output.extend_from_slice(CHUNK_END);
+        output.extend_from_slice(&0u32.to_le_bytes());
+        output
+    }
+
+    // Restores a state from save_state. Nothing is changed if the state can't be read.
+    // Sections missing from older states keep their current values.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let (version, chunks) = read_chunks(data)?;
+        let chunks = migrate(version, chunks)?;
+
+        let cpu: CpuState = require(decode(&chunks, CHUNK_CPU)?, CHUNK_CPU)?;
+        let memory: MemoryState = require(decode(&chunks, CHUNK_MEMORY)?, CHUNK_MEMORY)?;
+        let isr: Option<Isr> = decode(&chunks, CHUNK_ISR)?;
+        let ppu: Option<PpuState> = decode(&chunks, CHUNK_PPU)?;
+        let serial: Option<SerialState> = decode(&chunks, CHUNK_SERIAL)?;
+        let gbs: Option<GbsState> = decode(&chunks, CHUNK_GBS)?;
+        let joypad: Option<u8> = decode(&chunks, CHUNK_JOYPAD)?;
+        let counters: Option<Counters> = decode(&chunks, CHUNK_COUNTERS)?;
+        let cheats: Option<Cheats> = decode(&chunks, CHUNK_CHEATS)?;
+        if memory.main.len() != self.memory.main.len() || memory.boot_rom.len() != self.memory.rom.len() {
+            return Err(StateError::BadChunk(*CHUNK_MEMORY));
+        }
+        if let Some(ppu) = ppu.as_ref() {
+            if ppu.display.len() != self.display.len() || ppu.display_temp.len() != self.display_temp.len() {
+                return Err(StateError::BadChunk(*CHUNK_PPU));
+            }
+        }
+
+        self.registers = cpu.registers;
+        self.clock = cpu.clock;
+        self.running = cpu.running;
+        self.cycles_to_idle = cpu.cycles_to_idle;
+        self.ime = cpu.ime;
+        self.ime_dispatch = cpu.ime_dispatch;
+
+        self.memory.mapping_type = memory.mapping_type;
+        self.memory.main.copy_from_slice(&memory.main);
+        self.memory.rom.copy_from_slice(&memory.boot_rom);
+        self.memory.rom_bank = memory.rom_bank;
+
+        if let Some(isr) = isr {
+            self.isr = isr;
+        }
+        if let Some(ppu) = ppu {
+            self.window_line_counter = ppu.window_line_counter;
+            self.display_temp.copy_from_slice(&ppu.display_temp);
+            self.display.copy_from_slice(&ppu.display);
+        }
+        if let Some(serial) = serial {
+            self.serial.load_state(serial, self.clock);
+        }
+        if let (Some(player), Some(gbs)) = (self.gbs.as_mut(), gbs) {
+            player.load_state(gbs);
+        }
+        if let Some(joypad) = joypad {
+            self.memory.joypad = joypad;
+        }
+        if let Some(counters) = counters {
+            self.frame_count = counters.frame_count;
+            self.instructions = counters.instructions;
+        }
+        if let Some(cheats) = cheats {
+            self.memory.cheats = cheats;
+        }
+        self.finish_restore();
+        Ok(())
+    }
+
+    // Rebuilds what states don't store from what they do, after loading any kind of state
+    pub(crate) fn finish_restore(&mut self) {
+        self.reschedule_events(); // also marks the interrupt requests stale
+        self.memory.serial_stale = true;
+        self.shadow_stack.clear(); // the frames were tracked on another timeline
+    }
+}
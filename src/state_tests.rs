@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod state_test {
+    use crate::memory::Memory;
+    use crate::state::{StateError, STATE_VERSION};
+    use crate::util::{fingerprint, test_gameboy, COUNTING_LOOP};
+
+    #[test]
+    fn restores_identical_execution() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        gameboy.add_cheat("3E1-50F").unwrap();
+        for _ in 0..40000 {
+            gameboy.tick();
+        }
+        let state = gameboy.save_state();
+        for _ in 0..30000 {
+            gameboy.tick();
+        }
+        let expected = fingerprint(&gameboy);
+
+        let mut restored = test_gameboy(&COUNTING_LOOP);
+        restored.load_state(&state).expect("Could not load state");
+        for _ in 0..30000 {
+            restored.tick();
+        }
+        assert!(fingerprint(&restored) == expected, "execution diverged after loading state");
+        assert_eq!(restored.memory.read(0x0150), 0x3E, "cheats should be restored");
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let mut gameboy = test_gameboy(&COUNTING_LOOP);
+        let state = gameboy.save_state();
+        assert_eq!(gameboy.load_state(&state[..state.len() - 20]), Err(StateError::Truncated));
+        assert_eq!(gameboy.load_state(b"NOTSTATE\x01\x00"), Err(StateError::BadMagic));
+
+        let mut newer = state.clone();
+        newer[8..10].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        assert_eq!(gameboy.load_state(&newer), Err(StateError::UnsupportedVersion(STATE_VERSION + 1)));
+        newer[8..10].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(gameboy.load_state(&newer), Err(StateError::UnsupportedVersion(0)));
+    }
+
+}
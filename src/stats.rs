@@ -0,0 +1,158 @@
+// Optional instrumentation: counts executed opcodes, tick() calls while stopped, interrupts
+// serviced and CPU reads and writes per memory region. Off by default; start_stats() turns it on
+// and stats() takes a snapshot.
+//
+// Opcode fetches count as reads. Like watchpoints, accesses by the PPU or the host don't count.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use std::cell::Cell;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Region {
+    Rom,     // 0000-7FFF
+    Vram,    // 8000-9FFF
+    CartRam, // A000-BFFF
+    Wram,    // C000-DFFF
+    Echo,    // E000-FDFF
+    Oam,     // FE00-FE9F
+    Unusable, // FEA0-FEFF
+    Io,      // FF00-FF7F
+    Hram,    // FF80-FFFE
+    Ie,      // FFFF
+}
+
+pub const REGIONS: [Region; 10] = [
+    Region::Rom,
+    Region::Vram,
+    Region::CartRam,
+    Region::Wram,
+    Region::Echo,
+    Region::Oam,
+    Region::Unusable,
+    Region::Io,
+    Region::Hram,
+    Region::Ie,
+];
+
+impl Region {
+    pub fn of(address: u16) -> Region {
+        match address {
+            0x0000..=0x7FFF => Region::Rom,
+            0x8000..=0x9FFF => Region::Vram,
+            0xA000..=0xBFFF => Region::CartRam,
+            0xC000..=0xDFFF => Region::Wram,
+            0xE000..=0xFDFF => Region::Echo,
+            0xFE00..=0xFE9F => Region::Oam,
+            0xFEA0..=0xFEFF => Region::Unusable,
+            0xFF00..=0xFF7F => Region::Io,
+            0xFF80..=0xFFFE => Region::Hram,
+            0xFFFF => Region::Ie,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stats {
+    pub opcodes: [u64; 256],
+    pub cb_opcodes: [u64; 256], // the second byte of CB prefixed instructions
+    pub instructions: u64,
+    // Host tick() calls while the CPU was stopped. HALT doesn't wake up in this core and the run
+    // API returns HaltedForever instead of ticking, so only raw tick() calls count here.
+    pub halt_ticks: u64,
+    pub interrupts: [u64; 5], // VBlank, STAT, timer, serial, joypad
+    pub reads: [u64; 10], // indexed like REGIONS
+    pub writes: [u64; 10],
+}
+
+// CPU accesses, counted in MappedRAM
+#[derive(Default)]
+pub struct AccessCounts {
+    reads: [Cell<u64>; 10],
+    writes: [Cell<u64>; 10],
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats {
+            opcodes: [0; 256],
+            cb_opcodes: [0; 256],
+            instructions: 0,
+            halt_ticks: 0,
+            interrupts: [0; 5],
+            reads: [0; 10],
+            writes: [0; 10],
+        }
+    }
+}
+
+impl Stats {
+    pub fn reads_from(&self, region: Region) -> u64 {
+        self.reads[region as usize]
+    }
+
+    pub fn writes_to(&self, region: Region) -> u64 {
+        self.writes[region as usize]
+    }
+}
+
+impl AccessCounts {
+    pub(crate) fn read(&self, address: u16) {
+        let count = &self.reads[Region::of(address) as usize];
+        count.set(count.get() + 1);
+    }
+
+    pub(crate) fn write(&self, address: u16) {
+        let count = &self.writes[Region::of(address) as usize];
+        count.set(count.get() + 1);
+    }
+}
+
+impl GameBoy {
+    // Starts counting from zero
+    pub fn start_stats(&mut self) {
+        self.stats = Some(Stats::default());
+        self.memory.access_counts = Some(AccessCounts::default());
+    }
+
+    pub fn stop_stats(&mut self) -> Option<Stats> {
+        let stats = self.stats();
+        self.stats = None;
+        self.memory.access_counts = None;
+        stats
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        let mut stats = self.stats.clone()?;
+        if let Some(counts) = self.memory.access_counts.as_ref() {
+            stats.reads = counts.reads.each_ref().map(Cell::get);
+            stats.writes = counts.writes.each_ref().map(Cell::get);
+        }
+        Some(stats)
+    }
+
+    // Called when `opcode` has been fetched from PC - 1
+    pub(crate) fn count_instruction(&mut self, opcode: u8) {
+        let pc = self.registers.pc;
+        let cb_opcode = match opcode {
+            0xCB => Some(self.memory.read(pc)),
+            _ => None,
+        };
+        if let Some(counts) = self.memory.access_counts.as_ref() {
+            counts.read(pc.wrapping_sub(1));
+        }
+        if let Some(stats) = self.stats.as_mut() {
+            stats.instructions += 1;
+            stats.opcodes[opcode as usize] += 1;
+            if let Some(cb_opcode) = cb_opcode {
+                stats.cb_opcodes[cb_opcode as usize] += 1;
+            }
+        }
+    }
+
+    pub(crate) fn count_interrupt(&mut self, vector: u16) {
+        let index = (vector as usize).wrapping_sub(0x40) / 8;
+        if let Some(count) = self.stats.as_mut().and_then(|stats| stats.interrupts.get_mut(index)) {
+            *count += 1;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod stats_test {
+    use crate::run::StopReason;
+    use crate::stats::Region;
+    use crate::util::test_gameboy_with;
+
+    #[test]
+    fn counts_opcodes_interrupts_and_accesses() {
+        let program = [
+            0x21, 0x00, 0xC0, // 0100: LD HL, 0xC000
+            0x77, // 0103: LD (HL), A
+            0xCB, 0x37, // 0104: SWAP A
+            0xE0, 0x80, // 0106: LDH (0xFF80), A
+            0xFB, // 0108: EI
+            0x00, // 0109: NOP
+            0x76, // 010A: HALT
+        ];
+        let mut gameboy = test_gameboy_with(&[(0x100, &program), (0x50, &[0xD9])]); // timer vector: RETI
+        gameboy.memory.main[0xFFFF] = 0x04; // timer interrupt enabled
+        gameboy.start_stats();
+        let mut requested = false;
+        while gameboy.running {
+            if gameboy.registers.pc == 0x0109 && !requested {
+                gameboy.memory.main[0xFF0F] = 0x04;
+                requested = true;
+            }
+            gameboy.tick();
+        }
+        for _ in 0..10 {
+            gameboy.tick();
+        }
+        assert_eq!(gameboy.run_frame(), StopReason::HaltedForever); // doesn't tick
+
+        let stats = gameboy.stop_stats().unwrap();
+        assert_eq!(stats.instructions, 8);
+        assert_eq!(stats.opcodes[0xCB], 1);
+        assert_eq!(stats.cb_opcodes[0x37], 1);
+        assert_eq!(stats.opcodes[0x76], 1);
+        assert_eq!(stats.interrupts, [0, 0, 1, 0, 0]);
+        assert_eq!(stats.halt_ticks, 10);
+        assert_eq!(stats.writes_to(Region::Wram), 1);
+        assert_eq!(stats.writes_to(Region::Hram), 3); // LDH, then the interrupt pushing PC
+        assert_eq!(stats.reads_from(Region::Rom), 12); // 8 opcodes and 4 operand bytes
+        assert_eq!(stats.writes_to(Region::Io), 1); // dispatch clearing IF
+        assert!(gameboy.stats().is_none());
+    }
+}
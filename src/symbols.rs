@@ -0,0 +1,122 @@
+// Symbol files as RGBLINK (-n) and no$gmb write them, one "bank:address label" per line in hex:
+//   00:0150 Main
+//   01:4000 LoadTiles.loop
+// ; starts a comment, and lines that aren't symbols (such as no$gmb's [labels]) are skipped.
+//
+// Addresses are resolved against the bank currently mapped there, so a label in ROM bank 3
+// only names 0x4000-0x7FFF while bank 3 is switched in. Regions without banking match any bank.
+use crate::debugger::Condition;
+use crate::disasm::Instruction;
+use crate::gb::GameBoy;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// Starts of the memory map regions; labels never name addresses past the end of their region
+const REGIONS: [u16; 9] = [0x0000, 0x4000, 0x8000, 0xA000, 0xC000, 0xD000, 0xE000, 0xFE00, 0xFF80];
+
+#[derive(Default)]
+pub struct Symbols {
+    labels: BTreeMap<(u16, u16), String>, // (address, bank) to the first label there
+    addresses: HashMap<String, (u16, u16)>, // label to (bank, address)
+}
+
+// The .sym file RGBLINK writes next to a ROM, e.g. game.gb to game.sym
+pub fn path_for(rom: &Path) -> PathBuf {
+    rom.with_extension("sym")
+}
+
+fn region_start(address: u16) -> u16 {
+    *REGIONS.iter().rev().find(|start| **start <= address).unwrap_or(&0)
+}
+
+fn parse_line(line: &str) -> Option<(u16, u16, &str)> {
+    let line = line.split(';').next()?;
+    let mut words = line.split_whitespace();
+    let (bank, address) = words.next()?.split_once(':')?;
+    let name = words.next()?;
+    Some((u16::from_str_radix(bank, 16).ok()?, u16::from_str_radix(address, 16).ok()?, name))
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for (bank, address, name) in text.lines().filter_map(parse_line) {
+            symbols.add(bank, address, name);
+        }
+        symbols
+    }
+
+    pub fn add(&mut self, bank: u16, address: u16, name: &str) {
+        self.labels.entry((address, bank)).or_insert_with(|| name.to_string());
+        self.addresses.entry(name.to_string()).or_insert((bank, address));
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    // (bank, address) of a label
+    pub fn lookup(&self, name: &str) -> Option<(u16, u16)> {
+        self.addresses.get(name).copied()
+    }
+
+    // The label at exactly this address. A bank of None matches any bank.
+    pub fn name_at(&self, address: u16, bank: Option<u16>) -> Option<&str> {
+        self.labels
+            .range((address, 0)..=(address, u16::MAX))
+            .find(|((_, label_bank), _)| bank.is_none_or(|bank| bank == *label_bank))
+            .map(|(_, name)| name.as_str())
+    }
+
+    // The closest label at or before this address in the same region, with the offset from it
+    pub fn nearest(&self, address: u16, bank: Option<u16>) -> Option<(&str, u16)> {
+        self.labels
+            .range((region_start(address), 0)..=(address, u16::MAX))
+            .rev()
+            .find(|((_, label_bank), _)| bank.is_none_or(|bank| bank == *label_bank))
+            .map(|((label_address, _), name)| (name.as_str(), address - label_address))
+    }
+
+    // "label" or "label+$N" for an address
+    pub fn describe(&self, address: u16, bank: Option<u16>) -> Option<String> {
+        match self.nearest(address, bank)? {
+            (name, 0) => Some(name.to_string()),
+            (name, offset) => Some(format!("{}+${:X}", name, offset)),
+        }
+    }
+}
+
+impl GameBoy {
+    pub fn load_symbols(&mut self, text: &str) {
+        self.symbols = Symbols::parse(text);
+    }
+
+    // The label at an address as the CPU currently sees it
+    pub fn symbol_at(&self, address: u16) -> Option<&str> {
+        self.symbols.name_at(address, self.memory.bank(address))
+    }
+
+    // "label" or "label+$N" for an address as the CPU currently sees it
+    pub fn describe_address(&self, address: u16) -> Option<String> {
+        self.symbols.describe(address, self.memory.bank(address))
+    }
+
+    // Breaks at a label, only while its bank is mapped. None if there's no such label.
+    pub fn add_symbol_breakpoint(&mut self, name: &str, condition: Option<Condition>) -> Option<u32> {
+        let (bank, address) = self.symbols.lookup(name)?;
+        let id = self.add_breakpoint(address, condition);
+        if let Some(breakpoint) = self.debugger.breakpoints.iter_mut().find(|breakpoint| breakpoint.id == id) {
+            breakpoint.bank = Some(bank);
+        }
+        Some(id)
+    }
+
+    // Prints an instruction like its Display impl, with labels for the addresses it refers to
+    pub fn format_instruction(&self, instruction: &Instruction) -> String {
+        instruction.format_with(&|address| self.symbol_at(address).map(str::to_string))
+    }
+}
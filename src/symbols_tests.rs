@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod symbols_test {
+    use crate::gb;
+    use crate::run::StopReason;
+    use crate::symbols::Symbols;
+    use crate::trace::TraceOptions;
+    use crate::util::SharedLog;
+
+    const SYM: &str = "; File generated by rgblink
+00:0100 Main
+00:0100 EntryPoint
+00:0103 Main.loop
+01:4000 BankedRoutine
+02:4000 OtherBank
+00:c000 wCounter
+00:ff80 hFlags
+[labels]
+";
+
+    #[test]
+    fn parsing_and_lookup() {
+        let symbols = Symbols::parse(SYM);
+        assert_eq!(symbols.len(), 7);
+        assert_eq!(symbols.lookup("Main.loop"), Some((0, 0x0103)));
+        assert_eq!(symbols.name_at(0x0100, None), Some("Main")); // the first label wins
+        assert_eq!(symbols.name_at(0x4000, Some(2)), Some("OtherBank"));
+        assert_eq!(symbols.name_at(0x4000, Some(3)), None);
+        assert_eq!(symbols.nearest(0x0108, Some(0)), Some(("Main.loop", 5)));
+        assert_eq!(symbols.nearest(0xC010, None), Some(("wCounter", 0x10)));
+        // labels don't reach past their region
+        assert_eq!(symbols.nearest(0xFF40, None), None);
+    }
+
+    #[test]
+    fn banked_symbols_follow_the_mapping() {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0x0147] = 0x01; // MBC1
+        let program = [
+            0x3E, 0x02, // 0100: LD A, 2
+            0xEA, 0x00, 0x20, // 0102: LD (0x2000), A
+            0xCD, 0x00, 0x40, // 0105: CALL 0x4000
+            0x18, 0xFE, // 0108: JR -2
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        rom[0x8000] = 0xC9; // bank 2: RET
+        let mut gameboy = gb::init();
+        gameboy.memory.load_cartridge(rom);
+        gameboy.load_symbols(SYM);
+        let log = SharedLog::default();
+        gameboy.start_trace(Box::new(log.clone()), TraceOptions { labels: true, ..Default::default() });
+
+        assert_eq!(gameboy.symbol_at(0x4000), Some("BankedRoutine"));
+        assert_eq!(gameboy.describe_address(0x4003).as_deref(), Some("BankedRoutine+$3"));
+        let call = gameboy.disassemble(0x0105);
+        assert_eq!(gameboy.format_instruction(&call), "call BankedRoutine");
+        assert_eq!(call.to_string(), "call $4000");
+
+        // the program switches in bank 2 before the call, so only its label breaks
+        let other = gameboy.add_symbol_breakpoint("OtherBank", None).unwrap();
+        gameboy.add_symbol_breakpoint("BankedRoutine", None).unwrap();
+        assert_eq!(gameboy.add_symbol_breakpoint("Missing", None), None);
+        assert_eq!(gameboy.run_frame(), StopReason::Breakpoint(0x4000));
+        assert_eq!(gameboy.debugger.breakpoints.iter().find(|b| b.id == other).unwrap().hits, 1);
+        assert_eq!(gameboy.symbol_at(0x4000), Some("OtherBank"));
+        gameboy.stop_trace().unwrap();
+
+        let text = String::from_utf8(log.0.borrow().clone()).unwrap();
+        assert!(text.lines().last().unwrap().ends_with("PC:0105 PCMEM:CD,00,40,18 ; Main.loop+$2"));
+    }
+}
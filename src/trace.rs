@@ -0,0 +1,95 @@
+// Instruction traces in the Gameboy Doctor log format, one line before each instruction:
+//   A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+// https://github.com/robert/gameboy-doctor
+// Its reference logs start after the boot ROM and read LY as 0x90 throughout.
+use crate::gb::GameBoy;
+use crate::memory::Memory;
+use std::io::{self, Write};
+
+pub const DOCTOR_LY: u8 = 0x90;
+
+pub struct TraceOptions {
+    pub skip_boot_rom: bool, // start from the post boot ROM state at 0x100
+    pub stub_ly: bool, // the CPU reads DOCTOR_LY from LY
+    pub labels: bool, // append " ; label+$N" for the PC when there are symbols, see symbols.rs
+}
+
+impl Default for TraceOptions {
+    // What gameboy-doctor's reference logs expect
+    fn default() -> TraceOptions {
+        TraceOptions {
+            skip_boot_rom: true,
+            stub_ly: true,
+            labels: false,
+        }
+    }
+}
+
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    labels: bool,
+}
+
+impl GameBoy {
+    pub fn start_trace(&mut self, writer: Box<dyn Write>, options: TraceOptions) {
+        if options.skip_boot_rom {
+            self.skip_boot_rom();
+        }
+        if options.stub_ly {
+            self.memory.ly_stub = Some(DOCTOR_LY);
+        }
+        self.tracer = Some(Tracer {
+            writer,
+            labels: options.labels,
+        });
+    }
+
+    // Stops tracing and un-stubs LY, returning the flushed writer
+    pub fn stop_trace(&mut self) -> io::Result<Option<Box<dyn Write>>> {
+        self.memory.ly_stub = None;
+        match self.tracer.take() {
+            Some(mut tracer) => {
+                tracer.writer.flush()?;
+                Ok(Some(tracer.writer))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn trace_line(&self) -> String {
+        let pc = self.registers.pc;
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.registers.a,
+            self.registers.f,
+            self.registers.b,
+            self.registers.c,
+            self.registers.d,
+            self.registers.e,
+            self.registers.h,
+            self.registers.l,
+            self.registers.sp,
+            pc,
+            self.memory.read(pc),
+            self.memory.read(pc.wrapping_add(1)),
+            self.memory.read(pc.wrapping_add(2)),
+            self.memory.read(pc.wrapping_add(3)),
+        )
+    }
+
+    pub(crate) fn trace_instruction(&mut self) {
+        let mut line = self.trace_line();
+        if self.tracer.as_ref().is_some_and(|tracer| tracer.labels) {
+            if let Some(label) = self.describe_address(self.registers.pc) {
+                line.push_str(" ; ");
+                line.push_str(&label);
+            }
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            if let Err(error) = writeln!(tracer.writer, "{}", line) {
+                self.logger.log_warning(&format!("Trace: stopped, could not write: {}", error));
+                self.tracer = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+#[cfg(test)]
+mod trace_test {
+    use crate::trace::TraceOptions;
+    use crate::util::{test_gameboy, SharedLog};
+
+    #[test]
+    fn logs_gameboy_doctor_lines() {
+        let program = [
+            0x00, // NOP
+            0xF0, 0x44, // LDH A, (LY)
+            0x06, 0x42, // LD B, 0x42
+            0x18, 0xFE, // JR -2
+        ];
+        let mut gameboy = test_gameboy(&program);
+        let log = SharedLog::default();
+        gameboy.start_trace(Box::new(log.clone()), TraceOptions::default());
+        for _ in 0..12 {
+            gameboy.tick();
+        }
+        gameboy.stop_trace().unwrap();
+
+        let text = String::from_utf8(log.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[..4], [
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,F0,44,06",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:F0,44,06,42",
+            "A:90 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103 PCMEM:06,42,18,FE",
+            "A:90 F:B0 B:42 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0105 PCMEM:18,FE,00,00",
+        ]);
+        assert_eq!(gameboy.memory.ly_stub, None);
+    }
+}
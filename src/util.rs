@@ -12,6 +12,84 @@ pub fn unsigned_16(msb: u8, lsb: u8) -> u16 {
     ((msb as u16) << 8) | lsb as u16
 }
 
+// CRC-32 (IEEE 802.3), used to identify ROMs
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFFFFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ 0xEDB88320,
+            };
+        }
+    }
+    !crc
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReadError {
+    Truncated,
+    Overflow, // a variable-length number too big for usize
+}
+
+// A cursor for parsing binary files. Integers are little-endian unless the name says otherwise.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pub offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], offset: usize) -> Reader<'a> {
+        Reader { data, offset }
+    }
+
+    pub fn take(&mut self, length: usize) -> Result<&'a [u8], ReadError> {
+        let end = self.offset.checked_add(length).ok_or(ReadError::Truncated)?;
+        let bytes = self.data.get(self.offset..end).ok_or(ReadError::Truncated)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ReadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16, ReadError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u24_be(&mut self) -> Result<u32, ReadError> {
+        let bytes = self.take(3)?;
+        Ok((bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32)
+    }
+
+    // A UPS/BPS variable-length number: 7 bits per byte, low first, ending at a byte with bit 7
+    // set. Each continuation also adds the next power of 128, so every number has one encoding.
+    pub fn number(&mut self) -> Result<usize, ReadError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.u8()?;
+            let digit = ((byte & 0x7F) as usize).checked_mul(shift).ok_or(ReadError::Overflow)?;
+            number = number.checked_add(digit).ok_or(ReadError::Overflow)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(128).ok_or(ReadError::Overflow)?;
+            number = number.checked_add(shift).ok_or(ReadError::Overflow)?;
+        }
+    }
+}
+
 impl GameBoy {
     pub fn set_flag_z(&mut self, value: bool) {
         match value {
@@ -189,3 +267,64 @@ impl GameBoy {
         }
     }
 }
+
+// Test fixtures: a Game Boy past the boot ROM, with a 32K cartridge holding each (address, bytes)
+// piece of `code`
+#[cfg(test)]
+pub fn test_gameboy_with(code: &[(usize, &[u8])]) -> GameBoy {
+    let mut rom = vec![0u8; 0x8000];
+    for (address, bytes) in code {
+        rom[*address..*address + bytes.len()].copy_from_slice(bytes);
+    }
+    let mut gameboy = crate::gb::init();
+    gameboy.memory.load_cartridge(rom);
+    gameboy.skip_boot_rom();
+    gameboy
+}
+
+// The same with just a program at the entry point, 0x100
+#[cfg(test)]
+pub fn test_gameboy(program: &[u8]) -> GameBoy {
+    test_gameboy_with(&[(0x100, program)])
+}
+
+// Counts up in A, storing each count to the next byte from 0xC000
+#[cfg(test)]
+pub const COUNTING_LOOP: [u8; 8] = [
+    0x21, 0x00, 0xC0, // LD HL, 0xC000
+    0x3C, // INC A
+    0x77, // LD (HL), A
+    0x2C, // INC L
+    0x18, 0xFB, // JR -5
+];
+
+// What a restored state has to reproduce, for comparing two runs
+#[cfg(test)]
+pub fn fingerprint(gameboy: &GameBoy) -> (u16, u8, u128, u64, u64, Vec<u8>, Vec<u8>) {
+    (
+        gameboy.registers.pc,
+        gameboy.registers.a,
+        gameboy.clock,
+        gameboy.frame_count,
+        gameboy.instructions,
+        gameboy.memory.main.to_vec(),
+        gameboy.display.to_vec(),
+    )
+}
+
+// A trace sink the test can still read after handing a clone to the emulator
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct SharedLog(pub std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedLog {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}